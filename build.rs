@@ -1,3 +1,32 @@
+use std::{env, fs, path::Path};
+
 fn main() {
     println!("cargo:rerun-if-changed=migrations");
+    // Embeds every migration, sorted by version, in $OUT_DIR/migrations.rs
+    let mut migrations: Vec<(u64, String)> = fs::read_dir("migrations")
+        .expect("Failed to read migrations directory")
+        .map(|entry| entry.expect("Failed to read migration").path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "sql"))
+        .map(|path| {
+            let name = path.file_name().unwrap().to_string_lossy().to_string();
+            let version = name
+                .split('_')
+                .next()
+                .and_then(|version| version.parse().ok())
+                .unwrap_or_else(|| panic!("Invalid migration name: {}", name));
+            let path = fs::canonicalize(&path).expect("Failed to get migration's path");
+            (version, path.to_string_lossy().to_string())
+        })
+        .collect();
+    migrations.sort();
+    let mut code = String::from("pub const MIGRATIONS: &[(i64, &str)] = &[\n");
+    for (version, path) in migrations {
+        code.push_str(&format!("    ({}, include_str!({:?})),\n", version, path));
+    }
+    code.push_str("];\n");
+    fs::write(
+        Path::new(&env::var("OUT_DIR").unwrap()).join("migrations.rs"),
+        code,
+    )
+    .expect("Failed to write migrations.rs");
 }
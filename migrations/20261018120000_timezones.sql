@@ -0,0 +1,16 @@
+ALTER TABLE birthdays ADD COLUMN timezone TEXT DEFAULT 'UTC' NOT NULL;
+CREATE UNIQUE INDEX IF NOT EXISTS birthdays_user_group ON birthdays(user_id, group_id);
+CREATE TABLE IF NOT EXISTS user_timezones
+(
+    id              INTEGER PRIMARY KEY NOT NULL,
+    user_id         REAL                NOT NULL,
+    timezone        TEXT                NOT NULL,
+    UNIQUE(user_id)
+);
+CREATE TABLE IF NOT EXISTS group_timezones
+(
+    id              INTEGER PRIMARY KEY NOT NULL,
+    group_id        INTEGER             NOT NULL,
+    timezone        TEXT                NOT NULL,
+    UNIQUE(group_id)
+);
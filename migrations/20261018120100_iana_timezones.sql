@@ -0,0 +1,16 @@
+-- Timezones used to be stored as whole-hour UTC offsets
+UPDATE birthdays SET timezone = CASE
+    WHEN timezone = 0 THEN 'UTC'
+    WHEN timezone > 0 THEN 'Etc/GMT-' || timezone
+    ELSE 'Etc/GMT+' || -timezone
+END WHERE typeof(timezone) = 'integer';
+UPDATE user_timezones SET timezone = CASE
+    WHEN timezone = 0 THEN 'UTC'
+    WHEN timezone > 0 THEN 'Etc/GMT-' || timezone
+    ELSE 'Etc/GMT+' || -timezone
+END WHERE typeof(timezone) = 'integer';
+UPDATE group_timezones SET timezone = CASE
+    WHEN timezone = 0 THEN 'UTC'
+    WHEN timezone > 0 THEN 'Etc/GMT-' || timezone
+    ELSE 'Etc/GMT+' || -timezone
+END WHERE typeof(timezone) = 'integer';
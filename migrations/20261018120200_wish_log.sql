@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS wish_log
+(
+    id              INTEGER PRIMARY KEY NOT NULL,
+    user_id         REAL                NOT NULL,
+    group_id        INTEGER             NOT NULL,
+    year            INTEGER             NOT NULL,
+    UNIQUE(user_id, group_id, year)
+);
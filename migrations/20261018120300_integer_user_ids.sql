@@ -0,0 +1,42 @@
+-- User IDs used to be stored as REAL, which is lossy for big IDs
+CREATE TABLE birthdays_new
+(
+    id              INTEGER PRIMARY KEY NOT NULL,
+    user_id         INTEGER             NOT NULL,
+    group_id        INTEGER             NOT NULL,
+    user_lang       TEXT                NOT NULL,
+    year            INTEGER             NOT NULL,
+    month           INTEGER             NOT NULL,
+    day             INTEGER             NOT NULL,
+    timezone        TEXT DEFAULT 'UTC'  NOT NULL,
+    UNIQUE(user_id, group_id)
+);
+INSERT INTO birthdays_new (id, user_id, group_id, user_lang, year, month, day, timezone)
+    SELECT id, CAST(user_id AS INTEGER), group_id, user_lang, year, month, day, timezone FROM birthdays;
+DROP TABLE birthdays;
+ALTER TABLE birthdays_new RENAME TO birthdays;
+
+CREATE TABLE user_timezones_new
+(
+    id              INTEGER PRIMARY KEY NOT NULL,
+    user_id         INTEGER             NOT NULL,
+    timezone        TEXT                NOT NULL,
+    UNIQUE(user_id)
+);
+INSERT INTO user_timezones_new (id, user_id, timezone)
+    SELECT id, CAST(user_id AS INTEGER), timezone FROM user_timezones;
+DROP TABLE user_timezones;
+ALTER TABLE user_timezones_new RENAME TO user_timezones;
+
+CREATE TABLE wish_log_new
+(
+    id              INTEGER PRIMARY KEY NOT NULL,
+    user_id         INTEGER             NOT NULL,
+    group_id        INTEGER             NOT NULL,
+    year            INTEGER             NOT NULL,
+    UNIQUE(user_id, group_id, year)
+);
+INSERT INTO wish_log_new (id, user_id, group_id, year)
+    SELECT id, CAST(user_id AS INTEGER), group_id, year FROM wish_log;
+DROP TABLE wish_log;
+ALTER TABLE wish_log_new RENAME TO wish_log;
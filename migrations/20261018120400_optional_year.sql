@@ -0,0 +1,17 @@
+-- The year of birth is optional
+CREATE TABLE birthdays_new
+(
+    id              INTEGER PRIMARY KEY NOT NULL,
+    user_id         INTEGER             NOT NULL,
+    group_id        INTEGER             NOT NULL,
+    user_lang       TEXT                NOT NULL,
+    year            INTEGER,
+    month           INTEGER             NOT NULL,
+    day             INTEGER             NOT NULL,
+    timezone        TEXT DEFAULT 'UTC'  NOT NULL,
+    UNIQUE(user_id, group_id)
+);
+INSERT INTO birthdays_new (id, user_id, group_id, user_lang, year, month, day, timezone)
+    SELECT id, user_id, group_id, user_lang, year, month, day, timezone FROM birthdays;
+DROP TABLE birthdays;
+ALTER TABLE birthdays_new RENAME TO birthdays;
@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS group_leap_days
+(
+    id              INTEGER PRIMARY KEY NOT NULL,
+    group_id        INTEGER             NOT NULL,
+    march_first     INTEGER             NOT NULL DEFAULT 0,
+    UNIQUE(group_id)
+);
@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS usernames
+(
+    user_id         INTEGER PRIMARY KEY NOT NULL,
+    username        TEXT                NOT NULL COLLATE NOCASE,
+    UNIQUE(username)
+);
@@ -0,0 +1,19 @@
+-- Birthdays added by someone else wait here until the user confirms them
+CREATE TABLE IF NOT EXISTS pending_birthdays
+(
+    id              INTEGER PRIMARY KEY NOT NULL,
+    user_id         INTEGER             NOT NULL,
+    group_id        INTEGER             NOT NULL,
+    user_lang       TEXT                NOT NULL,
+    year            INTEGER,
+    month           INTEGER             NOT NULL,
+    day             INTEGER             NOT NULL,
+    message_id      INTEGER,
+    created_at      INTEGER             NOT NULL,
+    UNIQUE(user_id, group_id)
+);
+CREATE TABLE IF NOT EXISTS group_settings
+(
+    group_id            INTEGER PRIMARY KEY NOT NULL,
+    admins_skip_consent INTEGER             NOT NULL DEFAULT 0
+);
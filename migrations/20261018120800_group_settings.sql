@@ -0,0 +1,12 @@
+ALTER TABLE group_settings ADD COLUMN pin INTEGER NOT NULL DEFAULT 1;
+-- Minutes after midnight, in the group's timezone
+ALTER TABLE group_settings ADD COLUMN wish_time INTEGER NOT NULL DEFAULT 0;
+-- NULL means that every user gets messages in their own language
+ALTER TABLE group_settings ADD COLUMN language TEXT;
+ALTER TABLE group_settings ADD COLUMN admins_only_add INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE group_settings ADD COLUMN show_age INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE group_settings ADD COLUMN march_first INTEGER NOT NULL DEFAULT 0;
+INSERT INTO group_settings (group_id, march_first)
+    SELECT group_id, march_first FROM group_leap_days WHERE true
+    ON CONFLICT(group_id) DO UPDATE SET march_first = excluded.march_first;
+DROP TABLE group_leap_days;
@@ -0,0 +1,17 @@
+CREATE TABLE IF NOT EXISTS group_reminders
+(
+    group_id        INTEGER             NOT NULL,
+    -- Days before the birthday
+    days            INTEGER             NOT NULL,
+    UNIQUE(group_id, days)
+);
+CREATE TABLE IF NOT EXISTS reminder_log
+(
+    id              INTEGER PRIMARY KEY NOT NULL,
+    user_id         INTEGER             NOT NULL,
+    group_id        INTEGER             NOT NULL,
+    -- Year of the birthday the reminder was sent for
+    year            INTEGER             NOT NULL,
+    days            INTEGER             NOT NULL,
+    UNIQUE(user_id, group_id, year, days)
+);
@@ -0,0 +1,21 @@
+CREATE TABLE IF NOT EXISTS subscriptions
+(
+    user_id         INTEGER             NOT NULL,
+    group_id        INTEGER             NOT NULL,
+    user_lang       TEXT                NOT NULL,
+    -- Days before the birthday
+    days            INTEGER             NOT NULL,
+    -- Turned off when the user can't get private messages
+    active          INTEGER             NOT NULL DEFAULT 1,
+    UNIQUE(user_id, group_id)
+);
+CREATE TABLE IF NOT EXISTS subscription_log
+(
+    id              INTEGER PRIMARY KEY NOT NULL,
+    subscriber_id   INTEGER             NOT NULL,
+    user_id         INTEGER             NOT NULL,
+    group_id        INTEGER             NOT NULL,
+    -- Year of the birthday the reminder was sent for
+    year            INTEGER             NOT NULL,
+    UNIQUE(subscriber_id, user_id, group_id, year)
+);
@@ -0,0 +1,12 @@
+-- 0: off, 1: weekly, 2: monthly
+ALTER TABLE group_settings ADD COLUMN digest INTEGER NOT NULL DEFAULT 0;
+-- Whether birthdays are still wished on the day
+ALTER TABLE group_settings ADD COLUMN wishes INTEGER NOT NULL DEFAULT 1;
+CREATE TABLE IF NOT EXISTS digest_log
+(
+    id              INTEGER PRIMARY KEY NOT NULL,
+    group_id        INTEGER             NOT NULL,
+    -- First day of the week or month, as YYYY-MM-DD
+    date            TEXT                NOT NULL,
+    UNIQUE(group_id, date)
+);
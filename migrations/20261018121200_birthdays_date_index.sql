@@ -0,0 +1 @@
+CREATE INDEX IF NOT EXISTS birthdays_date ON birthdays(month, day);
@@ -0,0 +1,2 @@
+-- Requests to change a birthday that's already in the group
+ALTER TABLE pending_birthdays ADD COLUMN edit INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,3 @@
+-- Birthdays are wished in their group's timezone, users can't set their own anymore
+DROP TABLE IF EXISTS user_timezones;
+ALTER TABLE birthdays DROP COLUMN timezone;
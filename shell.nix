@@ -12,9 +12,6 @@ pkgs.mkShell {
     rustfmt
     pkg-config
     cargo-crev
-    python39
-    python39Packages.autopep8
-    virtualenv
     sqlx-cli
   ];
 
@@ -0,0 +1,72 @@
+// Copyright: https://github.com/hex0x0000/BirthdayBot/src/branch/master/LICENSE
+use crate::commands::birthdays_page;
+use crate::config::Config;
+use crate::consent;
+use crate::database::Database;
+use crate::date_picker::{self, DatePickers};
+use crate::lang::Langs;
+use crate::settings;
+use crate::Bot;
+use anyhow::Context;
+use std::sync::Arc;
+use teloxide::{prelude::*, ApiError, RequestError};
+
+pub async fn answer_callback(
+    bot: Bot,
+    query: CallbackQuery,
+    db: Arc<Database>,
+    labels: Arc<Langs>,
+    config: Arc<Config>,
+    pickers: Arc<DatePickers>,
+) -> anyhow::Result<()> {
+    let lang = match &query.message {
+        Some(message) => settings::chat_lang(&db, &message.chat, Some(&query.from)).await?,
+        None => settings::user_lang(Some(&query.from)),
+    };
+    let mut notification = None;
+    if let (Some(data), Some(message)) = (&query.data, &query.message) {
+        if let Some(page) = data.strip_prefix("birthdays ") {
+            let page: usize = page.parse().context("Invalid birthdays page")?;
+            if let Some((text, keyboard)) =
+                birthdays_page(&bot, &db, &labels, message.chat.id, &lang, page).await?
+            {
+                match bot
+                    .edit_message_text(message.chat.id, message.id, text)
+                    .reply_markup(keyboard)
+                    .await
+                {
+                    Ok(_) | Err(RequestError::Api(ApiError::MessageNotModified)) => {}
+                    Err(err) => return Err(err).context("Failed to edit BIRTHDAYS_LIST"),
+                }
+            }
+        } else if let Some(data) = data.strip_prefix("picker ") {
+            notification =
+                date_picker::answer(&bot, &db, &labels, &pickers, &query, data, &lang).await?;
+        } else if let Some(data) = data.strip_prefix("settings ") {
+            notification = settings::answer(&bot, &db, &labels, &query, data, &lang).await?;
+        } else if let Some(data) = data.strip_prefix("consent ") {
+            notification = consent::answer(
+                &bot,
+                &db,
+                &labels,
+                &query,
+                data,
+                &lang,
+                config.consent_expiry,
+            )
+            .await?;
+        }
+    }
+    let request = bot.answer_callback_query(query.id);
+    match notification {
+        Some(label) => {
+            request
+                .text(labels.get(&lang, label))
+                .show_alert(true)
+                .await
+        }
+        None => request.await,
+    }
+    .context("Failed to answer callback query")?;
+    Ok(())
+}
@@ -1,17 +1,37 @@
 // Copyright: https://github.com/hex0x0000/BirthdayBot/src/branch/master/LICENSE
-use std::num::ParseIntError;
-
-use crate::database::RemoveBirthday;
-use crate::globals::{Bot, DB, LABELS};
+use crate::consent;
+use crate::database::{Birthday, Database, LeapDay, RemoveBirthday};
+use crate::date::{self, DateError};
+use crate::date_picker::{self, DatePickers};
+use crate::lang::Langs;
 use crate::send;
+use crate::settings;
+use crate::subscriptions;
+use crate::timezone::parse_timezone;
+use crate::usernames::is_username_valid;
+use crate::Bot;
 use anyhow::Context;
-use teloxide::types::{MessageEntityKind, ParseMode};
+use chrono::{Days, NaiveDate, NaiveTime, Utc};
+use chrono_tz::Tz;
+use std::sync::Arc;
+use teloxide::types::{
+    InlineKeyboardButton, InlineKeyboardMarkup, MessageEntityKind, MessageKind, ParseMode,
+};
 use teloxide::{
     prelude::*,
-    types::{ChatMember, Me},
+    types::{Administrator, ChatMember, ChatMemberKind, Me, Restricted},
     utils::command::BotCommands,
 };
 
+const BIRTHDAYS_PAGE_SIZE: usize = 20;
+const DEFAULT_UPCOMING_DAYS: u32 = 30;
+const MAX_UPCOMING_DAYS: u32 = 366;
+/// Telegram's limit on the length of a message's text
+const MAX_MESSAGE_LENGTH: usize = 4096;
+const MAX_REMINDER_DAYS: u32 = 366;
+const MAX_REMINDERS: usize = 5;
+const DEFAULT_REMINDME_DAYS: u32 = 1;
+
 #[derive(BotCommands, Clone, Debug)]
 #[command(description = "Bot's commands:")]
 pub enum Command {
@@ -22,21 +42,30 @@ pub enum Command {
     #[command(description = "bot's info", rename = "lowercase")]
     Info,
     #[command(
-        description = "adds your birthday (YYYY/MM/DD). Example /addmybirthday 2000/01/01",
+        description = "adds your birthday (e.g. 2000/01/31, 31.01.2000 or 31 January 2000, the year is optional), or lets you pick it if no date is given. Example /addmybirthday 2000/01/01",
         rename = "lowercase"
     )]
     AddMyBirthday(String),
     #[command(
-        description = "adds someone else's birthday (YYYY/MM/DD). Example /addbirthday @user 2000/01/01",
-        parse_with = "split",
+        description = "adds someone else's birthday (same formats as /addmybirthday), tagging them or replying to their message, they'll have to confirm it. Example /addbirthday @user 2000/01/01",
         rename = "lowercase"
     )]
-    AddBirthday { username: String, date: String },
+    AddBirthday(String),
     #[command(
         description = "removes your birthday from a group",
         rename = "lowercase"
     )]
     RemoveMyBirthday,
+    #[command(
+        description = "changes your birthday (same formats as /addmybirthday), in every group from private chat. Example /editmybirthday 2000/01/01",
+        rename = "lowercase"
+    )]
+    EditMyBirthday(String),
+    #[command(
+        description = "changes someone else's birthday (admins only), they'll have to confirm it. Example /editbirthday @user 2000/01/01",
+        rename = "lowercase"
+    )]
+    EditBirthday(String),
     #[command(
         description = "removes all the group's birthdays (admins only)",
         rename = "lowercase"
@@ -47,36 +76,423 @@ pub enum Command {
         rename = "lowercase"
     )]
     RemoveAllMyBirthdays,
+    #[command(
+        description = "sets the group's timezone (admins only). Example /setgrouptimezone +2",
+        rename = "lowercase"
+    )]
+    SetGroupTimezone(String),
+    #[command(
+        description = "sets the time of the day birthdays are wished at, in the group's timezone (admins only). Example /setwishtime 09:00",
+        rename = "lowercase"
+    )]
+    SetWishTime(String),
+    #[command(
+        description = "sets how many days before birthdays the group is reminded of them, or off (admins only). Example /setreminders 7 1",
+        rename = "lowercase"
+    )]
+    SetReminders(String),
+    #[command(
+        description = "sets when Feb 29 birthdays are wished in common years, 28/02 or 01/03 (admins only). Example /setleapday 01/03",
+        rename = "lowercase"
+    )]
+    SetLeapDay(String),
+    #[command(
+        description = "lets admins add and change birthdays without the user's confirmation, on or off (admins only). Example /skipconsent on",
+        rename = "lowercase"
+    )]
+    SkipConsent(String),
+    #[command(
+        description = "shows the group's settings menu (admins only)",
+        rename = "lowercase"
+    )]
+    Settings,
+    #[command(
+        description = "lists the birthdays added to the group",
+        rename = "lowercase"
+    )]
+    Birthdays,
+    #[command(
+        description = "shows the birthdays in the next days (30 by default). Example /upcoming 7",
+        rename = "lowercase"
+    )]
+    Upcoming(String),
+    #[command(
+        description = "shows your birthday in the group, or in every group from private chat",
+        rename = "lowercase"
+    )]
+    MyBirthday,
+    #[command(
+        description = "privately reminds you of the group's birthdays some days before (1 by default), or off. Example /remindme 3",
+        rename = "lowercase"
+    )]
+    RemindMe(String),
+}
+
+/// Says how many days are left until a birthday
+pub fn countdown(labels: &Langs, lang: &str, days: i64) -> String {
+    match days {
+        0 => labels.get(lang, "COUNTDOWN_TODAY"),
+        1 => labels.get(lang, "COUNTDOWN_TOMORROW"),
+        days => {
+            labels
+                .render(lang, "COUNTDOWN_DAYS", &[("days", days.into())])
+                .text
+        }
+    }
+}
+
+/// Parses the days before birthdays when reminders are sent, sorted from the earliest.
+/// `off` turns reminders off.
+fn parse_reminders(reminders: &str) -> Option<Vec<u32>> {
+    if reminders.trim().eq_ignore_ascii_case("off") {
+        return Some(vec![]);
+    }
+    let mut days: Vec<u32> = reminders
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|days| !days.is_empty())
+        .map(|days| {
+            days.parse()
+                .ok()
+                .filter(|days| (1..=MAX_REMINDER_DAYS).contains(days))
+        })
+        .collect::<Option<_>>()?;
+    days.sort_unstable_by(|a, b| b.cmp(a));
+    days.dedup();
+    if days.is_empty() || days.len() > MAX_REMINDERS {
+        return None;
+    }
+    Some(days)
+}
+
+/// Parses a date, understanding month names both in English and in the user's language
+fn parse_date(labels: &Langs, date: &str, lang: &str) -> Result<(Option<u32>, u8, u8), DateError> {
+    date::parse_date(
+        date,
+        &[labels.get(lang, "MONTHS"), labels.get("en", "MONTHS")],
+    )
 }
 
-pub async fn answer(bot: Bot, message: Message, command: Command) -> anyhow::Result<()> {
+/// Returns the user mentioned in the message, either with a text mention or with @username,
+/// along with the arguments after the mention, since a text mention's name may contain spaces.
+/// If the user can't be found the reason is sent to the chat and `None` is returned.
+async fn mentioned_user(
+    bot: &Bot,
+    db: &Database,
+    labels: &Langs,
+    message: &Message,
+    args: &str,
+    lang: &str,
+) -> anyhow::Result<Option<(UserId, String)>> {
+    for entity in message.parse_entities().unwrap_or_default() {
+        let Some((_, rest)) = args.split_once(entity.text()) else {
+            continue;
+        };
+        let rest = rest.trim().to_string();
+        match entity.kind() {
+            MessageEntityKind::Mention => {
+                let username = entity.text().trim_start_matches('@');
+                if !is_username_valid(username) {
+                    send!(bot, labels, message.chat.id, lang, "ERR_USERNAME_INVALID");
+                    return Ok(None);
+                }
+                return match db.get_user_id(username).await? {
+                    Some(id) => Ok(Some((id, rest))),
+                    None => {
+                        send!(bot, labels, message.chat.id, lang, "ERR_USER_NOT_FOUND");
+                        Ok(None)
+                    }
+                };
+            }
+            MessageEntityKind::TextMention { user } => return Ok(Some((user.id, rest))),
+            _ => {}
+        }
+    }
+    send!(bot, labels, message.chat.id, lang, "ERR_TAG");
+    Ok(None)
+}
+
+/// Checks if the user is one of the group's admins
+pub async fn is_admin(bot: &Bot, group_id: ChatId, user_id: UserId) -> anyhow::Result<bool> {
+    Ok(bot
+        .get_chat_administrators(group_id)
+        .await?
+        .iter()
+        .any(|admin| admin.user.id == user_id))
+}
+
+/// Returns the sender of the message if they're one of the group's admins.
+/// Otherwise the reason is sent to the chat and `None` is returned.
+async fn require_admin(
+    bot: &Bot,
+    labels: &Langs,
+    message: &Message,
+    lang: &str,
+) -> anyhow::Result<Option<UserId>> {
+    let Some(user) = message.from() else {
+        send!(bot, labels, message.chat.id, lang, "ERR_COULDNT_GET_USERID");
+        return Ok(None);
+    };
+    if !is_admin(bot, message.chat.id, user.id).await? {
+        send!(bot, labels, message.chat.id, lang, "ERR_DENIED");
+        return Ok(None);
+    }
+    Ok(Some(user.id))
+}
+
+/// Returns the current date in the group's timezone
+async fn group_today(db: &Database, group_id: ChatId) -> anyhow::Result<NaiveDate> {
+    let timezone: Tz = db
+        .get_group_timezone(group_id)
+        .await?
+        .parse()
+        .unwrap_or(Tz::UTC);
+    Ok(Utc::now().with_timezone(&timezone).date_naive())
+}
+
+/// Builds the list of birthdays in the group within the next `days` days,
+/// each with a countdown. Returns no messages if there are none.
+async fn upcoming_birthdays(
+    bot: &Bot,
+    db: &Database,
+    labels: &Langs,
+    group_id: ChatId,
+    lang: &str,
+    days: u32,
+) -> anyhow::Result<Vec<String>> {
+    let today = group_today(db, group_id).await?;
+    let last_day = today
+        .checked_add_days(Days::new(days.into()))
+        .context("Failed to get last day")?;
+    let entries = birthday_entries(bot, db, labels, group_id, lang, today, last_day).await?;
+    if entries.is_empty() {
+        return Ok(vec![]);
+    }
+    let header = labels
+        .render(lang, "UPCOMING_BIRTHDAYS", &[("days", days.into())])
+        .text;
+    Ok(split_messages(header, entries))
+}
+
+/// Writes the entries one per line after the header, split into as many messages
+/// as needed to stay within Telegram's length limit
+pub fn split_messages(header: String, entries: Vec<String>) -> Vec<String> {
+    let mut messages = vec![header];
+    for entry in entries {
+        let text = messages.last_mut().expect("There's always a message");
+        if text.chars().count() + entry.chars().count() < MAX_MESSAGE_LENGTH {
+            text.push('\n');
+            text.push_str(&entry);
+        } else {
+            messages.push(entry);
+        }
+    }
+    messages
+}
+
+/// Lists the birthdays in the group from `today` to `last_day`, sorted by date,
+/// each with a countdown
+pub async fn birthday_entries(
+    bot: &Bot,
+    db: &Database,
+    labels: &Langs,
+    group_id: ChatId,
+    lang: &str,
+    today: NaiveDate,
+    last_day: NaiveDate,
+) -> anyhow::Result<Vec<String>> {
+    let settings = db.get_group_settings(group_id).await?;
+    let mut birthdays: Vec<(NaiveDate, Birthday)> = db
+        .get_group_birthdays_between(group_id, today, last_day)
+        .await?
+        .into_iter()
+        .filter_map(|birthday| {
+            Some((
+                birthday.next_occurrence(today, settings.leap_day)?,
+                birthday,
+            ))
+        })
+        .filter(|(date, _)| *date <= last_day)
+        .collect();
+    birthdays.sort_by_key(|(date, _)| *date);
+    let mut entries = vec![];
+    for (date, birthday) in &birthdays {
+        let name = match bot.get_chat_member(group_id, birthday.user_id).await {
+            Ok(member) => member.user.full_name(),
+            Err(err) => {
+                log::error!("Failed to get {}: {:?}", birthday.user_id, err);
+                continue;
+            }
+        };
+        let countdown = countdown(labels, lang, (*date - today).num_days());
+        let args = [
+            ("name", name.into()),
+            (
+                "date",
+                format!("{}/{}", birthday.day, birthday.month).into(),
+            ),
+            ("countdown", countdown.into()),
+        ];
+        let entry = match birthday.age(*date).filter(|_| settings.show_age) {
+            Some(age) => labels.render(
+                lang,
+                "UPCOMING_ENTRY",
+                &[&args[..], &[("age", age.into())]].concat(),
+            ),
+            None => labels.render(lang, "UPCOMING_ENTRY_NO_YEAR", &args),
+        };
+        entries.push(entry.text);
+    }
+    Ok(entries)
+}
+
+/// Builds a page of the group's birthdays, sorted by their next occurrence,
+/// with buttons to move between pages. Returns `None` if the group has no birthdays.
+pub async fn birthdays_page(
+    bot: &Bot,
+    db: &Database,
+    labels: &Langs,
+    group_id: ChatId,
+    lang: &str,
+    page: usize,
+) -> anyhow::Result<Option<(String, InlineKeyboardMarkup)>> {
+    let today = group_today(db, group_id).await?;
+    let settings = db.get_group_settings(group_id).await?;
+    let mut birthdays: Vec<(NaiveDate, Birthday)> = db
+        .get_group_birthdays(group_id)
+        .await?
+        .into_iter()
+        .filter_map(|birthday| {
+            Some((
+                birthday.next_occurrence(today, settings.leap_day)?,
+                birthday,
+            ))
+        })
+        .collect();
+    if birthdays.is_empty() {
+        return Ok(None);
+    }
+    birthdays.sort_by_key(|(date, _)| *date);
+    let pages = birthdays.len().div_ceil(BIRTHDAYS_PAGE_SIZE);
+    let page = page.min(pages - 1);
+    let mut text = labels
+        .render(
+            lang,
+            "BIRTHDAYS_LIST",
+            &[("page", (page + 1).into()), ("pages", pages.into())],
+        )
+        .text;
+    for (date, birthday) in birthdays
+        .iter()
+        .skip(page * BIRTHDAYS_PAGE_SIZE)
+        .take(BIRTHDAYS_PAGE_SIZE)
+    {
+        let name = match bot.get_chat_member(group_id, birthday.user_id).await {
+            Ok(member) => member.user.full_name(),
+            Err(err) => {
+                log::error!("Failed to get {}: {:?}", birthday.user_id, err);
+                continue;
+            }
+        };
+        let args = [
+            ("name", name.into()),
+            (
+                "date",
+                format!("{}/{}", birthday.day, birthday.month).into(),
+            ),
+        ];
+        let entry = match birthday.age(*date).filter(|_| settings.show_age) {
+            Some(age) => labels.render(
+                lang,
+                "BIRTHDAYS_LIST_ENTRY",
+                &[&args[..], &[("age", age.into())]].concat(),
+            ),
+            None => labels.render(lang, "BIRTHDAYS_LIST_ENTRY_NO_YEAR", &args),
+        };
+        text.push('\n');
+        text.push_str(&entry.text);
+    }
+    let mut buttons = vec![];
+    if page > 0 {
+        buttons.push(InlineKeyboardButton::callback(
+            "⬅️",
+            format!("birthdays {}", page - 1),
+        ));
+    }
+    if page + 1 < pages {
+        buttons.push(InlineKeyboardButton::callback(
+            "➡️",
+            format!("birthdays {}", page + 1),
+        ));
+    }
+    Ok(Some((text, InlineKeyboardMarkup::new([buttons]))))
+}
+
+fn can_pin_messages(member: &ChatMember) -> bool {
+    match &member.kind {
+        ChatMemberKind::Owner(_) => true,
+        ChatMemberKind::Administrator(Administrator {
+            can_pin_messages, ..
+        })
+        | ChatMemberKind::Restricted(Restricted {
+            can_pin_messages, ..
+        }) => *can_pin_messages,
+        _ => false,
+    }
+}
+
+async fn send_invalid_timezone(
+    bot: &Bot,
+    labels: &Langs,
+    chat_id: ChatId,
+    lang: &str,
+    suggestions: Vec<&str>,
+) -> anyhow::Result<()> {
+    if suggestions.is_empty() {
+        send!(bot, labels, chat_id, lang, "ERR_INVALID_TIMEZONE");
+    } else {
+        send!(
+            bot,
+            labels,
+            chat_id,
+            lang,
+            "ERR_UNKNOWN_TIMEZONE",
+            suggestions = suggestions.join("\n")
+        );
+    }
+    Ok(())
+}
+
+pub async fn answer(
+    bot: Bot,
+    message: Message,
+    command: Command,
+    db: Arc<Database>,
+    labels: Arc<Langs>,
+    pickers: Arc<DatePickers>,
+) -> anyhow::Result<()> {
     let me: Me = bot.get_me().await?;
     let me: ChatMember = bot.get_chat_member(message.chat.id, me.id).await?;
-    let lang: String = match message.from() {
-        Some(user) => match user.language_code.clone() {
-            Some(lang) => lang,
-            None => "en".to_string(),
-        },
-        None => "en".to_string(),
-    };
+    let user_lang = settings::user_lang(message.from());
+    let lang = settings::chat_lang(&db, &message.chat, message.from()).await?;
     log::info!("Issued command: {:?}", command);
     match command {
         Command::Help => {
-            send!(bot, message.chat.id, &lang, "HELP");
+            send!(bot, labels, message.chat.id, &lang, "HELP");
         }
         Command::Start => {
             if message.chat.is_group() || message.chat.is_supergroup() {
-                if !me.can_pin_messages() {
-                    send!(bot, message.chat.id, &lang, "NO_PIN_PERM");
+                let settings = db.get_group_settings(message.chat.id).await?;
+                if settings.pin && !can_pin_messages(&me) {
+                    send!(bot, labels, message.chat.id, &lang, "NO_PIN_PERM");
                 } else {
-                    send!(bot, message.chat.id, &lang, "START_MESSAGE_GRP");
+                    send!(bot, labels, message.chat.id, &lang, "START_MESSAGE_GRP");
                 }
             } else {
-                send!(bot, message.chat.id, &lang, "START_MESSAGE_PVT");
+                send!(bot, labels, message.chat.id, &lang, "START_MESSAGE_PVT");
             }
         }
         Command::Info => {
-            bot.send_message(message.chat.id, LABELS.get(&lang, "INFO"))
+            bot.send_message(message.chat.id, labels.get(&lang, "INFO"))
                 .parse_mode(ParseMode::Html)
                 .disable_web_page_preview(true)
                 .await
@@ -84,194 +500,621 @@ pub async fn answer(bot: Bot, message: Message, command: Command) -> anyhow::Res
         }
         Command::AddMyBirthday(date) => {
             if message.chat.is_group() || message.chat.is_supergroup() {
-                let (year, month, day) = match {
-                    let list: Vec<Result<u16, ParseIntError>> =
-                        date.split('/').map(|x| x.parse::<u16>()).collect();
-                    let (year, month, day) = (list[0].clone()?, list[1].clone()?, list[2].clone()?);
-                    if (month < 1 || month > 12) || (day < 1 || day > 31) {
-                        Err(anyhow::anyhow!("Invalid date"))
+                if date.trim().is_empty() {
+                    let user_id = if let Some(user) = message.from() {
+                        user.id
                     } else {
-                        Ok((year, month, day))
-                    }
-                } {
+                        send!(
+                            bot,
+                            labels,
+                            message.chat.id,
+                            &lang,
+                            "ERR_COULDNT_GET_USERID"
+                        );
+                        return Ok(());
+                    };
+                    date_picker::start(&bot, &labels, &pickers, message.chat.id, user_id, &lang)
+                        .await?;
+                    return Ok(());
+                }
+                let (year, month, day) = match parse_date(&labels, &date, &lang) {
                     Ok(var) => var,
-                    Err(_) => {
-                        send!(bot, message.chat.id, &lang, "ERR_INVALID_DATE");
+                    Err(err) => {
+                        send!(bot, labels, message.chat.id, &lang, err.label());
                         return Ok(());
                     }
                 };
-                let was_added = DB
-                    .get()
-                    .await
-                    .add_birthday(
-                        if let Some(user) = message.from() {
-                            user.id.0 as f64
+                let was_added = db
+                    .add_birthday(Birthday {
+                        user_id: if let Some(user) = message.from() {
+                            user.id
                         } else {
-                            send!(bot, message.chat.id, &lang, "ERR_COULDNT_GET_USERID");
+                            send!(
+                                bot,
+                                labels,
+                                message.chat.id,
+                                &lang,
+                                "ERR_COULDNT_GET_USERID"
+                            );
                             return Ok(());
                         },
-                        message.chat.id.0,
-                        &lang,
+                        group_id: message.chat.id,
+                        user_lang: user_lang.clone(),
                         year,
                         month,
                         day,
-                    )
+                    })
                     .await?;
                 if was_added {
-                    send!(bot, message.chat.id, &lang, "BIRTHDAY_ADD_SUCCESS");
+                    send!(bot, labels, message.chat.id, &lang, "BIRTHDAY_ADD_SUCCESS");
                 } else {
-                    send!(bot, message.chat.id, &lang, "BIRTHDAY_EXISTS");
+                    send!(bot, labels, message.chat.id, &lang, "BIRTHDAY_EXISTS");
                 }
             } else {
-                send!(bot, message.chat.id, &lang, "ERR_ONLY_GROUPS");
+                send!(bot, labels, message.chat.id, &lang, "ERR_ONLY_GROUPS");
             }
         }
-        Command::AddBirthday { username, date } => {
+        Command::AddBirthday(args) => {
             if message.chat.is_group() || message.chat.is_supergroup() {
-                let (year, month, day) = match {
-                    let list: Vec<Result<u16, ParseIntError>> =
-                        date.split('/').map(|x| x.parse::<u16>()).collect();
-                    let (year, month, day) = (list[0].clone()?, list[1].clone()?, list[2].clone()?);
-                    if (month < 1 || month > 12) || (day < 1 || day > 31) {
-                        Err(anyhow::anyhow!("Invalid date"))
-                    } else {
-                        Ok((year, month, day))
+                // Replying to someone's message adds their birthday, otherwise they must be tagged.
+                // In forum topics every message replies to the topic's creation, which doesn't count.
+                let replied_user = message
+                    .reply_to_message()
+                    .filter(|reply| {
+                        !matches!(reply.kind, MessageKind::ForumTopicCreated(_))
+                            && message.thread_id != Some(reply.id.0)
+                    })
+                    .and_then(|reply| reply.from())
+                    .filter(|user| !user.is_bot);
+                let (user_id, date) = match replied_user {
+                    Some(user) => (user.id, args.trim().to_string()),
+                    None => {
+                        if args.trim().is_empty() {
+                            send!(bot, labels, message.chat.id, &lang, "ERR_TAG_OR_REPLY");
+                            return Ok(());
+                        }
+                        match mentioned_user(&bot, &db, &labels, &message, &args, &lang).await? {
+                            Some(mentioned) => mentioned,
+                            None => return Ok(()),
+                        }
                     }
-                } {
+                };
+                let (year, month, day) = match parse_date(&labels, &date, &lang) {
                     Ok(var) => var,
-                    Err(_) => {
-                        send!(bot, message.chat.id, &lang, "ERR_INVALID_DATE");
+                    Err(err) => {
+                        send!(bot, labels, message.chat.id, &lang, err.label());
                         return Ok(());
                     }
                 };
-                if let Some(entities) = message.entities() {
-                    for entity in entities {
-                        match entity.kind.clone() {
-                            MessageEntityKind::Mention => {
-                                if is_username_valid(&username) {
-                                    let id = match get_id(&username).await {
-                                        Ok(id) => id,
-                                        Err(err) => {
-                                            log::error!(
-                                                "Failed to get {}'s id. Error: {}. Root cause: {}",
-                                                username,
-                                                err,
-                                                err.root_cause()
-                                            );
-                                            send!(
-                                                bot,
-                                                message.chat.id,
-                                                &lang,
-                                                "ERR_COULDNT_GET_USERID"
-                                            );
-                                            return Ok(());
-                                        }
-                                    };
-                                    if id == 0.0 {
-                                        send!(bot, message.chat.id, &lang, "ERR_USER_NOT_FOUND");
-                                        return Ok(());
-                                    }
-                                    let was_added = DB
-                                        .get()
-                                        .await
-                                        .add_birthday(
-                                            id,
-                                            message.chat.id.0,
-                                            &lang,
-                                            year,
-                                            month,
-                                            day,
-                                        )
-                                        .await?;
-                                    if was_added {
-                                        send!(bot, message.chat.id, &lang, "BIRTHDAY_ADD_SUCCESS");
-                                    } else {
-                                        send!(bot, message.chat.id, &lang, "BIRTHDAY_EXISTS");
-                                    }
-                                } else {
-                                    send!(bot, message.chat.id, &lang, "ERR_USERNAME_INVALID");
-                                }
-                                break;
-                            }
-                            MessageEntityKind::TextMention { user } => {
-                                DB.get()
-                                    .await
-                                    .add_birthday(
-                                        user.id.0 as f64,
-                                        message.chat.id.0,
-                                        &lang,
-                                        year,
-                                        month,
-                                        day,
-                                    )
-                                    .await?;
-                                send!(bot, message.chat.id, &lang, "BIRTHDAY_ADD_SUCCESS");
-                                break;
-                            }
-                            _ => {}
-                        }
+                let requester = if let Some(user) = message.from() {
+                    user
+                } else {
+                    send!(
+                        bot,
+                        labels,
+                        message.chat.id,
+                        &lang,
+                        "ERR_COULDNT_GET_USERID"
+                    );
+                    return Ok(());
+                };
+                if db
+                    .get_user_birthdays(user_id)
+                    .await?
+                    .iter()
+                    .any(|birthday| birthday.group_id == message.chat.id)
+                {
+                    send!(bot, labels, message.chat.id, &lang, "BIRTHDAY_EXISTS");
+                    return Ok(());
+                }
+                let birthday = Birthday {
+                    user_id,
+                    group_id: message.chat.id,
+                    user_lang: user_lang.clone(),
+                    year,
+                    month,
+                    day,
+                };
+                let settings = db.get_group_settings(message.chat.id).await?;
+                let is_admin = is_admin(&bot, message.chat.id, requester.id).await?;
+                if settings.admins_only_add && !is_admin && user_id != requester.id {
+                    send!(bot, labels, message.chat.id, &lang, "ERR_DENIED");
+                    return Ok(());
+                }
+                // The user must confirm the birthday, unless the group lets admins skip it
+                let skip_consent =
+                    user_id == requester.id || (settings.admins_skip_consent && is_admin);
+                if !skip_consent {
+                    consent::request(&bot, &db, &labels, requester, birthday, false, &lang).await?;
+                } else if db.add_birthday(birthday).await? {
+                    send!(bot, labels, message.chat.id, &lang, "BIRTHDAY_ADD_SUCCESS");
+                } else {
+                    send!(bot, labels, message.chat.id, &lang, "BIRTHDAY_EXISTS");
+                }
+            } else {
+                send!(bot, labels, message.chat.id, &lang, "ERR_ONLY_GROUPS");
+            }
+        }
+        Command::EditMyBirthday(date) => {
+            let user_id = if let Some(user) = message.from() {
+                user.id
+            } else {
+                send!(
+                    bot,
+                    labels,
+                    message.chat.id,
+                    &lang,
+                    "ERR_COULDNT_GET_USERID"
+                );
+                return Ok(());
+            };
+            let (year, month, day) = match parse_date(&labels, &date, &lang) {
+                Ok(var) => var,
+                Err(err) => {
+                    send!(bot, labels, message.chat.id, &lang, err.label());
+                    return Ok(());
+                }
+            };
+            // From private chat the birthday is changed in every group
+            let group_id = if message.chat.is_group() || message.chat.is_supergroup() {
+                Some(message.chat.id)
+            } else {
+                None
+            };
+            let was_edited = db
+                .edit_birthday(user_id, group_id, year, month, day)
+                .await?;
+            if was_edited {
+                send!(bot, labels, message.chat.id, &lang, "BIRTHDAY_EDIT_SUCCESS");
+            } else {
+                send!(
+                    bot,
+                    labels,
+                    message.chat.id,
+                    &lang,
+                    "ERR_BIRTHDAY_NOT_FOUND"
+                );
+            }
+        }
+        Command::EditBirthday(args) => {
+            if message.chat.is_group() || message.chat.is_supergroup() {
+                if require_admin(&bot, &labels, &message, &lang)
+                    .await?
+                    .is_none()
+                {
+                    return Ok(());
+                }
+                let Some(requester) = message.from() else {
+                    return Ok(());
+                };
+                let (user_id, date) =
+                    match mentioned_user(&bot, &db, &labels, &message, &args, &lang).await? {
+                        Some(mentioned) => mentioned,
+                        None => return Ok(()),
+                    };
+                let (year, month, day) = match parse_date(&labels, &date, &lang) {
+                    Ok(var) => var,
+                    Err(err) => {
+                        send!(bot, labels, message.chat.id, &lang, err.label());
+                        return Ok(());
                     }
+                };
+                let settings = db.get_group_settings(message.chat.id).await?;
+                if user_id != requester.id && !settings.admins_skip_consent {
+                    let Some(birthday) = db
+                        .get_user_birthdays(user_id)
+                        .await?
+                        .into_iter()
+                        .find(|birthday| birthday.group_id == message.chat.id)
+                    else {
+                        send!(
+                            bot,
+                            labels,
+                            message.chat.id,
+                            &lang,
+                            "ERR_BIRTHDAY_NOT_FOUND"
+                        );
+                        return Ok(());
+                    };
+                    let birthday = Birthday {
+                        year,
+                        month,
+                        day,
+                        ..birthday
+                    };
+                    consent::request(&bot, &db, &labels, requester, birthday, true, &lang).await?;
+                    return Ok(());
+                }
+                let was_edited = db
+                    .edit_birthday(user_id, Some(message.chat.id), year, month, day)
+                    .await?;
+                if was_edited {
+                    send!(bot, labels, message.chat.id, &lang, "BIRTHDAY_EDIT_SUCCESS");
+                } else {
+                    send!(
+                        bot,
+                        labels,
+                        message.chat.id,
+                        &lang,
+                        "ERR_BIRTHDAY_NOT_FOUND"
+                    );
                 }
             } else {
-                send!(bot, message.chat.id, &lang, "ERR_ONLY_GROUPS");
+                send!(bot, labels, message.chat.id, &lang, "ERR_ONLY_GROUPS");
             }
         }
         Command::RemoveMyBirthday => {
             if message.chat.is_group() || message.chat.is_supergroup() {
-                DB.get()
-                    .await
-                    .rm_birthday(RemoveBirthday::RemoveUserInGroup {
-                        group_id: message.chat.id.0,
-                        user_id: if let Some(user) = message.from() {
-                            user.id.0 as f64
-                        } else {
-                            send!(bot, message.chat.id, &lang, "ERR_COULDNT_GET_USERID");
-                            return Ok(());
-                        },
-                    })
-                    .await?;
-                send!(bot, message.chat.id, &lang, "DONE");
+                db.rm_birthday(RemoveBirthday::UserInGroup {
+                    group_id: message.chat.id,
+                    user_id: if let Some(user) = message.from() {
+                        user.id
+                    } else {
+                        send!(
+                            bot,
+                            labels,
+                            message.chat.id,
+                            &lang,
+                            "ERR_COULDNT_GET_USERID"
+                        );
+                        return Ok(());
+                    },
+                })
+                .await?;
+                send!(bot, labels, message.chat.id, &lang, "DONE");
             } else {
-                send!(bot, message.chat.id, &lang, "ERR_ONLY_GROUPS");
+                send!(bot, labels, message.chat.id, &lang, "ERR_ONLY_GROUPS");
             }
         }
         Command::RemoveGroup => {
             if message.chat.is_group() || message.chat.is_supergroup() {
-                let admins: Vec<u64> = bot
-                    .get_chat_administrators(message.chat.id)
+                if require_admin(&bot, &labels, &message, &lang)
                     .await?
-                    .iter()
-                    .map(|x| x.user.id.0)
-                    .collect();
-                let user_id = if let Some(user) = message.from() {
-                    user.id.0
+                    .is_some()
+                {
+                    db.rm_birthday(RemoveBirthday::Group(message.chat.id))
+                        .await?;
+                    send!(bot, labels, message.chat.id, &lang, "DONE");
+                }
+            } else {
+                send!(bot, labels, message.chat.id, &lang, "ERR_ONLY_GROUPS");
+            }
+        }
+        Command::RemoveAllMyBirthdays => {
+            if let Some(user) = message.from() {
+                db.rm_birthday(RemoveBirthday::User(user.id)).await?;
+                send!(bot, labels, message.chat.id, &lang, "DONE");
+            } else {
+                send!(
+                    bot,
+                    labels,
+                    message.chat.id,
+                    &lang,
+                    "ERR_COULDNT_GET_USERID"
+                );
+            }
+        }
+        Command::SetGroupTimezone(timezone) => {
+            if message.chat.is_group() || message.chat.is_supergroup() {
+                if require_admin(&bot, &labels, &message, &lang)
+                    .await?
+                    .is_none()
+                {
+                    return Ok(());
+                }
+                let timezone = match parse_timezone(&timezone) {
+                    Ok(timezone) => timezone,
+                    Err(suggestions) => {
+                        send_invalid_timezone(&bot, &labels, message.chat.id, &lang, suggestions)
+                            .await?;
+                        return Ok(());
+                    }
+                };
+                db.set_group_timezone(message.chat.id, timezone).await?;
+                send!(bot, labels, message.chat.id, &lang, "TIMEZONE_SET");
+            } else {
+                send!(bot, labels, message.chat.id, &lang, "ERR_ONLY_GROUPS");
+            }
+        }
+        Command::SetLeapDay(date) => {
+            if message.chat.is_group() || message.chat.is_supergroup() {
+                if require_admin(&bot, &labels, &message, &lang)
+                    .await?
+                    .is_none()
+                {
+                    return Ok(());
+                }
+                let leap_day = match parse_date(&labels, &date, &lang) {
+                    Ok((None, 2, 28)) => LeapDay::Feb28,
+                    Ok((None, 3, 1)) => LeapDay::Mar1,
+                    _ => {
+                        send!(bot, labels, message.chat.id, &lang, "ERR_INVALID_LEAP_DAY");
+                        return Ok(());
+                    }
+                };
+                let mut settings = db.get_group_settings(message.chat.id).await?;
+                settings.leap_day = leap_day;
+                db.set_group_settings(message.chat.id, settings).await?;
+                send!(bot, labels, message.chat.id, &lang, "LEAP_DAY_SET");
+            } else {
+                send!(bot, labels, message.chat.id, &lang, "ERR_ONLY_GROUPS");
+            }
+        }
+        Command::SetWishTime(time) => {
+            if message.chat.is_group() || message.chat.is_supergroup() {
+                if require_admin(&bot, &labels, &message, &lang)
+                    .await?
+                    .is_none()
+                {
+                    return Ok(());
+                }
+                let wish_time = match NaiveTime::parse_from_str(time.trim(), "%H:%M") {
+                    Ok(wish_time) => wish_time,
+                    Err(_) => {
+                        send!(bot, labels, message.chat.id, &lang, "ERR_INVALID_WISH_TIME");
+                        return Ok(());
+                    }
+                };
+                let mut settings = db.get_group_settings(message.chat.id).await?;
+                settings.wish_time = wish_time;
+                db.set_group_settings(message.chat.id, settings).await?;
+                let timezone = db.get_group_timezone(message.chat.id).await?;
+                send!(
+                    bot,
+                    labels,
+                    message.chat.id,
+                    &lang,
+                    "WISH_TIME_SET",
+                    time = wish_time.format("%H:%M").to_string(),
+                    timezone = timezone
+                );
+            } else {
+                send!(bot, labels, message.chat.id, &lang, "ERR_ONLY_GROUPS");
+            }
+        }
+        Command::SetReminders(reminders) => {
+            if message.chat.is_group() || message.chat.is_supergroup() {
+                if require_admin(&bot, &labels, &message, &lang)
+                    .await?
+                    .is_none()
+                {
+                    return Ok(());
+                }
+                let reminders = match parse_reminders(&reminders) {
+                    Some(reminders) => reminders,
+                    None => {
+                        send!(bot, labels, message.chat.id, &lang, "ERR_INVALID_REMINDERS");
+                        return Ok(());
+                    }
+                };
+                let mut settings = db.get_group_settings(message.chat.id).await?;
+                settings.reminders = reminders;
+                db.set_group_settings(message.chat.id, settings.clone())
+                    .await?;
+                if settings.reminders.is_empty() {
+                    send!(bot, labels, message.chat.id, &lang, "REMINDERS_OFF");
                 } else {
-                    send!(bot, message.chat.id, &lang, "ERR_COULDNT_GET_USERID");
+                    send!(
+                        bot,
+                        labels,
+                        message.chat.id,
+                        &lang,
+                        "REMINDERS_SET",
+                        days = settings::reminders_list(&settings.reminders)
+                    );
+                }
+            } else {
+                send!(bot, labels, message.chat.id, &lang, "ERR_ONLY_GROUPS");
+            }
+        }
+        Command::SkipConsent(switch) => {
+            if message.chat.is_group() || message.chat.is_supergroup() {
+                if require_admin(&bot, &labels, &message, &lang)
+                    .await?
+                    .is_none()
+                {
                     return Ok(());
+                }
+                let skip = match switch.trim().to_lowercase().as_str() {
+                    "on" => true,
+                    "off" => false,
+                    _ => {
+                        send!(bot, labels, message.chat.id, &lang, "ERR_INVALID_SWITCH");
+                        return Ok(());
+                    }
                 };
-                if admins.contains(&user_id) {
-                    DB.get()
-                        .await
-                        .rm_birthday(RemoveBirthday::RemoveGroup(message.chat.id.0))
-                        .await?;
-                    send!(bot, message.chat.id, &lang, "DONE");
+                let mut settings = db.get_group_settings(message.chat.id).await?;
+                settings.admins_skip_consent = skip;
+                db.set_group_settings(message.chat.id, settings).await?;
+                if skip {
+                    send!(bot, labels, message.chat.id, &lang, "SKIP_CONSENT_ON");
                 } else {
-                    send!(bot, message.chat.id, &lang, "ERR_DENIED");
+                    send!(bot, labels, message.chat.id, &lang, "SKIP_CONSENT_OFF");
+                }
+            } else {
+                send!(bot, labels, message.chat.id, &lang, "ERR_ONLY_GROUPS");
+            }
+        }
+        Command::Settings => {
+            if message.chat.is_group() || message.chat.is_supergroup() {
+                if require_admin(&bot, &labels, &message, &lang)
+                    .await?
+                    .is_none()
+                {
                     return Ok(());
                 }
+                settings::send_menu(&bot, &db, &labels, message.chat.id, &lang).await?;
             } else {
-                send!(bot, message.chat.id, &lang, "ERR_ONLY_GROUPS");
+                send!(bot, labels, message.chat.id, &lang, "ERR_ONLY_GROUPS");
             }
         }
-        Command::RemoveAllMyBirthdays => {
-            if let Some(user) = message.from() {
-                DB.get()
+        Command::Birthdays => {
+            if message.chat.is_group() || message.chat.is_supergroup() {
+                match birthdays_page(&bot, &db, &labels, message.chat.id, &lang, 0).await? {
+                    Some((text, keyboard)) => {
+                        bot.send_message(message.chat.id, text)
+                            .reply_markup(keyboard)
+                            .await
+                            .context("Failed to send BIRTHDAYS_LIST")?;
+                    }
+                    None => {
+                        send!(bot, labels, message.chat.id, &lang, "NO_BIRTHDAYS");
+                    }
+                }
+            } else {
+                send!(bot, labels, message.chat.id, &lang, "ERR_ONLY_GROUPS");
+            }
+        }
+        Command::MyBirthday => {
+            let user_id = if let Some(user) = message.from() {
+                user.id
+            } else {
+                send!(
+                    bot,
+                    labels,
+                    message.chat.id,
+                    &lang,
+                    "ERR_COULDNT_GET_USERID"
+                );
+                return Ok(());
+            };
+            let birthdays = db.get_user_birthdays(user_id).await?;
+            if message.chat.is_group() || message.chat.is_supergroup() {
+                match birthdays
+                    .iter()
+                    .find(|birthday| birthday.group_id == message.chat.id)
+                {
+                    Some(birthday) => {
+                        let timezone = db.get_group_timezone(birthday.group_id).await?;
+                        send!(
+                            bot,
+                            labels,
+                            message.chat.id,
+                            &lang,
+                            "MY_BIRTHDAY",
+                            date = birthday.date(),
+                            timezone = timezone
+                        );
+                    }
+                    None => {
+                        send!(bot, labels, message.chat.id, &lang, "NO_MY_BIRTHDAY");
+                    }
+                }
+            } else if birthdays.is_empty() {
+                send!(bot, labels, message.chat.id, &lang, "NO_MY_BIRTHDAYS");
+            } else {
+                let mut text = labels.get(&lang, "MY_BIRTHDAYS");
+                for birthday in birthdays {
+                    let title = match bot.get_chat(birthday.group_id).await {
+                        Ok(chat) => chat.title().unwrap_or_default().to_string(),
+                        Err(err) => {
+                            log::error!("Failed to get {}: {:?}", birthday.group_id, err);
+                            birthday.group_id.to_string()
+                        }
+                    };
+                    let timezone = db.get_group_timezone(birthday.group_id).await?;
+                    let entry = labels.render(
+                        &lang,
+                        "MY_BIRTHDAYS_ENTRY",
+                        &[
+                            ("group", title.into()),
+                            ("date", birthday.date().into()),
+                            ("timezone", timezone.into()),
+                        ],
+                    );
+                    text.push('\n');
+                    text.push_str(&entry.text);
+                }
+                bot.send_message(message.chat.id, text)
                     .await
-                    .rm_birthday(RemoveBirthday::RemoveUser(user.id.0 as f64))
+                    .context("Failed to send MY_BIRTHDAYS")?;
+            }
+        }
+        Command::RemindMe(days) => {
+            if message.chat.is_group() || message.chat.is_supergroup() {
+                let user_id = if let Some(user) = message.from() {
+                    user.id
+                } else {
+                    send!(
+                        bot,
+                        labels,
+                        message.chat.id,
+                        &lang,
+                        "ERR_COULDNT_GET_USERID"
+                    );
+                    return Ok(());
+                };
+                let days: u32 = match days.trim() {
+                    "" => DEFAULT_REMINDME_DAYS,
+                    off if off.eq_ignore_ascii_case("off") => {
+                        if db.unsubscribe(user_id, message.chat.id).await? {
+                            send!(bot, labels, message.chat.id, &lang, "REMINDME_OFF");
+                        } else {
+                            send!(bot, labels, message.chat.id, &lang, "ERR_NOT_SUBSCRIBED");
+                        }
+                        return Ok(());
+                    }
+                    days => match days.parse() {
+                        Ok(days) if (1..=MAX_REMINDER_DAYS).contains(&days) => days,
+                        _ => {
+                            send!(bot, labels, message.chat.id, &lang, "ERR_INVALID_REMINDME");
+                            return Ok(());
+                        }
+                    },
+                };
+                let group_title = message.chat.title().unwrap_or_default().to_string();
+                if !subscriptions::greet(&bot, &labels, user_id, group_title, &user_lang, days)
+                    .await?
+                {
+                    send!(bot, labels, message.chat.id, &lang, "ERR_START_PRIVATE");
+                    return Ok(());
+                }
+                db.subscribe(user_id, message.chat.id, user_lang, days)
                     .await?;
-                send!(bot, message.chat.id, &lang, "DONE");
+                send!(
+                    bot,
+                    labels,
+                    message.chat.id,
+                    &lang,
+                    "REMINDME_SET",
+                    days = days
+                );
+            } else {
+                send!(bot, labels, message.chat.id, &lang, "ERR_ONLY_GROUPS");
+            }
+        }
+        Command::Upcoming(days) => {
+            if message.chat.is_group() || message.chat.is_supergroup() {
+                let days: u32 = if days.trim().is_empty() {
+                    DEFAULT_UPCOMING_DAYS
+                } else {
+                    match days.trim().parse() {
+                        Ok(days) if days <= MAX_UPCOMING_DAYS => days,
+                        _ => {
+                            send!(bot, labels, message.chat.id, &lang, "ERR_INVALID_DAYS");
+                            return Ok(());
+                        }
+                    }
+                };
+                let messages =
+                    upcoming_birthdays(&bot, &db, &labels, message.chat.id, &lang, days).await?;
+                if messages.is_empty() {
+                    send!(
+                        bot,
+                        labels,
+                        message.chat.id,
+                        &lang,
+                        "NO_UPCOMING_BIRTHDAYS",
+                        days = days
+                    );
+                }
+                for text in messages {
+                    bot.send_message(message.chat.id, text)
+                        .await
+                        .context("Failed to send UPCOMING_BIRTHDAYS")?;
+                }
             } else {
-                send!(bot, message.chat.id, &lang, "ERR_COULDNT_GET_USERID");
+                send!(bot, labels, message.chat.id, &lang, "ERR_ONLY_GROUPS");
             }
         }
     }
@@ -0,0 +1,47 @@
+// Copyright: https://github.com/hex0x0000/BirthdayBot/src/branch/master/LICENSE
+use anyhow::Context;
+use chrono::TimeDelta;
+use std::env;
+
+/// Birthdays that started at most this many hours before startup are still wished
+const DEFAULT_CATCH_UP_HOURS: i64 = 12;
+/// Longer downtimes would make the first check go through too many days
+const MAX_CATCH_UP_HOURS: i64 = 7 * 24;
+/// Birthdays added by someone else must be confirmed by the user within this many hours
+const DEFAULT_CONSENT_EXPIRY_HOURS: i64 = 48;
+
+pub struct Config {
+    pub database_path: String,
+    pub catch_up: TimeDelta,
+    pub consent_expiry: TimeDelta,
+}
+
+impl Config {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let database_path = env::var("DATABASE_PATH").context("DATABASE_PATH env var not found")?;
+        let catch_up_hours: i64 = match env::var("CATCH_UP_HOURS") {
+            Ok(hours) => hours.parse().context("CATCH_UP_HOURS must be a number")?,
+            Err(_) => DEFAULT_CATCH_UP_HOURS,
+        };
+        if !(0..=MAX_CATCH_UP_HOURS).contains(&catch_up_hours) {
+            anyhow::bail!(
+                "CATCH_UP_HOURS must be between 0 and {}",
+                MAX_CATCH_UP_HOURS
+            );
+        }
+        let catch_up = TimeDelta::try_hours(catch_up_hours).context("CATCH_UP_HOURS is too big")?;
+        let consent_expiry_hours: i64 = match env::var("CONSENT_EXPIRY_HOURS") {
+            Ok(hours) => hours
+                .parse()
+                .context("CONSENT_EXPIRY_HOURS must be a number")?,
+            Err(_) => DEFAULT_CONSENT_EXPIRY_HOURS,
+        };
+        let consent_expiry = TimeDelta::try_hours(consent_expiry_hours)
+            .context("CONSENT_EXPIRY_HOURS is too big")?;
+        Ok(Self {
+            database_path,
+            catch_up,
+            consent_expiry,
+        })
+    }
+}
@@ -0,0 +1,175 @@
+// Copyright: https://github.com/hex0x0000/BirthdayBot/src/branch/master/LICENSE
+use crate::database::{Birthday, Database};
+use crate::lang::Langs;
+use crate::send;
+use crate::settings;
+use crate::template::Text;
+use crate::Bot;
+use anyhow::Context;
+use chrono::{TimeDelta, Utc};
+use teloxide::{
+    prelude::*,
+    types::{InlineKeyboardButton, InlineKeyboardMarkup, MessageId, User},
+    ApiError, RequestError,
+};
+
+/// Asks the user in the group to confirm the birthday added or changed by someone else,
+/// in the group's language
+pub async fn request(
+    bot: &Bot,
+    db: &Database,
+    labels: &Langs,
+    requester: &User,
+    birthday: Birthday,
+    edit: bool,
+    lang: &str,
+) -> anyhow::Result<()> {
+    let group_id = birthday.group_id;
+    let user = match bot.get_chat_member(group_id, birthday.user_id).await {
+        Ok(member) => member.user,
+        Err(err) => {
+            log::error!("Failed to get {}: {:?}", birthday.user_id, err);
+            send!(bot, labels, group_id, lang, "ERR_MEMBER_NOT_FOUND");
+            return Ok(());
+        }
+    };
+    let date = birthday.date();
+    let id = db
+        .add_pending_birthday(birthday, edit, Utc::now().timestamp())
+        .await?;
+    let text = labels.render(
+        lang,
+        if edit {
+            "CONSENT_EDIT_REQUEST"
+        } else {
+            "CONSENT_REQUEST"
+        },
+        &[
+            ("user", Text::mention(user.full_name(), user)),
+            ("requester", requester.full_name().into()),
+            ("date", date.into()),
+        ],
+    );
+    let keyboard = InlineKeyboardMarkup::new([[
+        InlineKeyboardButton::callback(
+            labels.get(lang, "CONSENT_CONFIRM"),
+            format!("consent confirm {}", id),
+        ),
+        InlineKeyboardButton::callback(
+            labels.get(lang, "CONSENT_REJECT"),
+            format!("consent reject {}", id),
+        ),
+    ]]);
+    let message = text
+        .send(bot, group_id)
+        .reply_markup(keyboard)
+        .await
+        .context("Failed to send CONSENT_REQUEST")?;
+    db.set_pending_message(id, message.id).await?;
+    Ok(())
+}
+
+/// Replaces the request's message and removes its buttons
+async fn close(
+    bot: &Bot,
+    chat_id: ChatId,
+    message_id: MessageId,
+    text: String,
+) -> anyhow::Result<()> {
+    match bot.edit_message_text(chat_id, message_id, text).await {
+        Ok(_) | Err(RequestError::Api(ApiError::MessageNotModified)) => Ok(()),
+        Err(err) => Err(err).context("Failed to edit CONSENT_REQUEST"),
+    }
+}
+
+/// Handles the user's answer to a request. Returns the label to show to the user
+/// if they can't answer it.
+pub async fn answer(
+    bot: &Bot,
+    db: &Database,
+    labels: &Langs,
+    query: &CallbackQuery,
+    data: &str,
+    lang: &str,
+    expiry: TimeDelta,
+) -> anyhow::Result<Option<&'static str>> {
+    let message = query
+        .message
+        .as_ref()
+        .context("Consent request without message")?;
+    let (action, id) = data.split_once(' ').context("Invalid consent answer")?;
+    let id: i64 = id.parse().context("Invalid consent request id")?;
+    match db.get_pending_user(id).await? {
+        Some(user_id) if user_id != query.from.id => return Ok(Some("CONSENT_NOT_YOURS")),
+        Some(_) => {}
+        None => {
+            close(
+                bot,
+                message.chat.id,
+                message.id,
+                labels.get(lang, "CONSENT_EXPIRED"),
+            )
+            .await?;
+            return Ok(None);
+        }
+    }
+    let created_after = (Utc::now() - expiry).timestamp();
+    let label = match db.take_pending_birthday(id, created_after).await? {
+        Some(pending) if action == "confirm" && pending.edit => {
+            let birthday = pending.birthday;
+            let was_edited = db
+                .edit_birthday(
+                    birthday.user_id,
+                    Some(birthday.group_id),
+                    birthday.year,
+                    birthday.month,
+                    birthday.day,
+                )
+                .await?;
+            if was_edited {
+                "BIRTHDAY_EDIT_SUCCESS"
+            } else {
+                "ERR_BIRTHDAY_NOT_FOUND"
+            }
+        }
+        Some(mut pending) if action == "confirm" => {
+            // The birthday belongs to the user who confirmed it, not to the requester
+            pending.birthday.user_lang = settings::user_lang(Some(&query.from));
+            if db.add_birthday(pending.birthday).await? {
+                "BIRTHDAY_ADD_SUCCESS"
+            } else {
+                "BIRTHDAY_EXISTS"
+            }
+        }
+        Some(pending) if pending.edit => "CONSENT_EDIT_REJECTED",
+        Some(_) => "CONSENT_REJECTED",
+        None => "CONSENT_EXPIRED",
+    };
+    close(bot, message.chat.id, message.id, labels.get(lang, label)).await?;
+    Ok(None)
+}
+
+/// Removes the requests that weren't answered in time
+pub async fn expire_requests(
+    bot: &Bot,
+    db: &Database,
+    labels: &Langs,
+    expiry: TimeDelta,
+) -> anyhow::Result<()> {
+    let created_before = (Utc::now() - expiry).timestamp();
+    for pending in db.take_expired_pending_birthdays(created_before).await? {
+        let Some(message_id) = pending.message_id else {
+            continue;
+        };
+        let lang = db
+            .get_group_settings(pending.birthday.group_id)
+            .await?
+            .language
+            .unwrap_or(pending.birthday.user_lang);
+        let text = labels.get(&lang, "CONSENT_EXPIRED");
+        if let Err(err) = close(bot, pending.birthday.group_id, message_id, text).await {
+            log::error!("Failed to expire consent request {}: {}", pending.id, err);
+        }
+    }
+    Ok(())
+}
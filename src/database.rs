@@ -1,109 +1,290 @@
 // Copyright: https://github.com/hex0x0000/BirthdayBot/src/branch/master/LICENSE
+use crate::migrations;
 use anyhow::Context;
 use async_sqlite::{
-    rusqlite::{params, OptionalExtension},
+    rusqlite::{self, params, OptionalExtension, Row},
     JournalMode, Pool, PoolBuilder,
 };
-use std::env;
+use chrono::{Datelike, NaiveDate, NaiveTime, Timelike};
+use chrono_tz::Tz;
+use teloxide::types::{ChatId, MessageId, UserId};
 
-#[derive(Debug)]
 pub struct Database {
     pool: Pool,
 }
 
 pub enum RemoveBirthday {
-    RemoveGroup(i64),
-    RemoveUser(f64),
-    RemoveUserInGroup { group_id: i64, user_id: f64 },
+    Group(ChatId),
+    User(UserId),
+    UserInGroup { group_id: ChatId, user_id: UserId },
+}
+
+/// Day on which Feb 29 birthdays are celebrated in common years
+#[derive(Clone, Copy, PartialEq)]
+pub enum LeapDay {
+    Feb28,
+    Mar1,
+}
+
+/// How often the group gets a message listing the next birthdays
+#[derive(Clone, Copy, PartialEq)]
+pub enum Digest {
+    Off,
+    /// Every Monday, for the week
+    Weekly,
+    /// Every 1st of the month, for the month
+    Monthly,
+}
+
+#[derive(Clone)]
+pub struct GroupSettings {
+    /// Whether wishes are pinned
+    pub pin: bool,
+    /// Time of the day when birthdays are wished, in the group's timezone
+    pub wish_time: NaiveTime,
+    /// Language of the group's messages, or `None` to use each user's language
+    pub language: Option<String>,
+    /// Whether only admins can add someone else's birthday
+    pub admins_only_add: bool,
+    /// Whether birthdays added by admins don't need to be confirmed
+    pub admins_skip_consent: bool,
+    pub show_age: bool,
+    pub leap_day: LeapDay,
+    /// Days before each birthday when the group is reminded of it, from the earliest
+    pub reminders: Vec<u32>,
+    pub digest: Digest,
+    /// Whether birthdays are wished on the day
+    pub wishes: bool,
+}
+
+impl Default for GroupSettings {
+    fn default() -> Self {
+        Self {
+            pin: true,
+            wish_time: NaiveTime::MIN,
+            language: None,
+            admins_only_add: false,
+            admins_skip_consent: false,
+            show_age: true,
+            leap_day: LeapDay::Feb28,
+            reminders: vec![],
+            digest: Digest::Off,
+            wishes: true,
+        }
+    }
 }
 
 pub struct Birthday {
-    pub user_id: f64,
-    pub group_id: i64,
+    pub user_id: UserId,
+    pub group_id: ChatId,
     pub user_lang: String,
-    pub year: u32,
+    pub year: Option<u32>,
     pub month: u8,
     pub day: u8,
-    pub timezone: i8,
+}
+
+/// Birthday added by someone else, waiting for the user's confirmation
+pub struct PendingBirthday {
+    pub id: i64,
+    pub birthday: Birthday,
+    pub message_id: Option<MessageId>,
+    /// Whether the request changes the user's birthday instead of adding it
+    pub edit: bool,
+}
+
+impl Birthday {
+    /// Returns the age turned on `date`, if the year of birth is known
+    pub fn age(&self, date: NaiveDate) -> Option<i32> {
+        self.year.map(|year| date.year() - year as i32)
+    }
+
+    /// Formats the birthday as YYYY/MM/DD, or DD/MM without the year of birth
+    pub fn date(&self) -> String {
+        match self.year {
+            Some(year) => format!("{}/{}/{}", year, self.month, self.day),
+            None => format!("{}/{}", self.day, self.month),
+        }
+    }
+
+    /// Returns the date on which the birthday is celebrated in `year`,
+    /// moving Feb 29 according to `leap_day` in common years
+    pub fn occurrence(&self, year: i32, leap_day: LeapDay) -> Option<NaiveDate> {
+        match NaiveDate::from_ymd_opt(year, self.month.into(), self.day.into()) {
+            Some(date) => Some(date),
+            None if self.month == 2 && self.day == 29 => match leap_day {
+                LeapDay::Feb28 => NaiveDate::from_ymd_opt(year, 2, 28),
+                LeapDay::Mar1 => NaiveDate::from_ymd_opt(year, 3, 1),
+            },
+            None => None,
+        }
+    }
+
+    /// Returns the first date on or after `today` when the birthday is celebrated.
+    /// Invalid dates return `None`.
+    pub fn next_occurrence(&self, today: NaiveDate, leap_day: LeapDay) -> Option<NaiveDate> {
+        (today.year()..=today.year() + 1)
+            .filter_map(|year| self.occurrence(year, leap_day))
+            .find(|date| *date >= today)
+    }
 }
 
 impl Database {
-    pub async fn new() -> anyhow::Result<Self> {
-        let database_path = env::var("DATABASE_PATH").context("DATABASE_PATH env var not found")?;
+    pub async fn new(database_path: &str) -> anyhow::Result<Self> {
         let pool = PoolBuilder::new()
             .path(database_path)
             .journal_mode(JournalMode::Wal)
             .open()
             .await
             .context("Failed to create DB connection")?;
-        pool.conn(|conn| {
-            conn.execute_batch(
-                "BEGIN;
-CREATE TABLE IF NOT EXISTS birthdays (
-    id              INTEGER PRIMARY KEY NOT NULL,
-    user_id         REAL                NOT NULL,
-    group_id        INTEGER             NOT NULL,
-    user_lang       TEXT                NOT NULL,
-    year            INTEGER             NOT NULL,
-    month           INTEGER             NOT NULL,
-    day             INTEGER             NOT NULL,
-    timezone        INTEGER DEFAULT 0   NOT NULL,
-    UNIQUE(user_id, group_id)
-);
-CREATE TABLE IF NOT EXISTS user-timezones (
-    id              INTEGER PRIMARY KEY NOT NULL,
-    user_id         REAL                NOT NULL,
-    timezone        INTEGER             NOT NULL,
-    UNIQUE(user_id)
-);
-CREATE TABLE IF NOT EXISTS group-timezones (
-    id              INTEGER PRIMARY KEY NOT NULL,
-    group_id        REAL                NOT NULL,
-    timezone        INTEGER             NOT NULL,
-    UNIQUE(group_id)
-);
-COMMIT;",
-            )
-        })
-        .await
-        .context("Failed to create database tables")?;
+        migrations::migrate(&pool)
+            .await
+            .context("Failed to migrate database")?;
         Ok(Self { pool })
     }
 
-    async fn get_timezone(&self, user_id: f64, group_id: i64) -> anyhow::Result<i8> {
-        let user_timezone: Option<i8> = self
+    pub async fn get_group_timezone(&self, group_id: ChatId) -> anyhow::Result<String> {
+        let group_timezone: Option<String> = self
             .pool
-            .conn(|conn| {
+            .conn(move |conn| {
                 conn.query_row(
-                    "SELECT timezone FROM user-timezones WHERE user_id=?1",
-                    [user_id],
-                    |row| row.get(0)?,
+                    "SELECT timezone FROM group_timezones WHERE group_id = ?1",
+                    [group_id.0],
+                    |row| row.get(0),
                 )
                 .optional()
             })
             .await
-            .context("Failed to get user's timezone")?;
-        if let Some(timezone) = user_timezone {
-            Ok(timezone)
-        } else {
-            let group_timezone: Option<i8> = self
-                .pool
-                .conn(|conn| {
-                    conn.query_row(
-                        "SELECT timezone FROM group-timezones WHERE group_id=?1",
-                        [group_id],
-                        |row| row.get(0)?,
-                    )
-                    .optional()
-                })
-                .await
-                .context("Failed to get group's timezone")?;
-            if let Some(timezone) = group_timezone {
-                Ok(timezone)
-            } else {
-                Ok(0)
-            }
-        }
+            .context("Failed to get group's timezone")?;
+        Ok(group_timezone.unwrap_or_else(|| Tz::UTC.name().to_string()))
+    }
+
+    pub async fn set_group_timezone(&self, group_id: ChatId, timezone: Tz) -> anyhow::Result<()> {
+        let (group_id, timezone) = (group_id.0, timezone.name());
+        self.pool
+            .conn(move |conn| {
+                conn.execute(
+                    "INSERT INTO group_timezones (group_id, timezone) VALUES (?1, ?2)
+                    ON CONFLICT(group_id) DO UPDATE SET timezone = excluded.timezone",
+                    params![group_id, timezone],
+                )
+            })
+            .await
+            .context("Failed to set group's timezone")?;
+        Ok(())
+    }
+
+    pub async fn get_group_settings(&self, group_id: ChatId) -> anyhow::Result<GroupSettings> {
+        let settings: Option<GroupSettings> = self
+            .pool
+            .conn(move |conn| {
+                conn.query_row(
+                    "SELECT pin, wish_time, language, admins_only_add, admins_skip_consent,
+                    show_age, march_first, digest, wishes FROM group_settings WHERE group_id = ?1",
+                    [group_id.0],
+                    |row| {
+                        let wish_time: u32 = row.get(1)?;
+                        let march_first: bool = row.get(6)?;
+                        let digest: u8 = row.get(7)?;
+                        Ok(GroupSettings {
+                            pin: row.get(0)?,
+                            wish_time: NaiveTime::from_num_seconds_from_midnight_opt(
+                                wish_time * 60,
+                                0,
+                            )
+                            .unwrap_or(NaiveTime::MIN),
+                            language: row.get(2)?,
+                            admins_only_add: row.get(3)?,
+                            admins_skip_consent: row.get(4)?,
+                            show_age: row.get(5)?,
+                            leap_day: if march_first {
+                                LeapDay::Mar1
+                            } else {
+                                LeapDay::Feb28
+                            },
+                            reminders: vec![],
+                            digest: match digest {
+                                1 => Digest::Weekly,
+                                2 => Digest::Monthly,
+                                _ => Digest::Off,
+                            },
+                            wishes: row.get(8)?,
+                        })
+                    },
+                )
+                .optional()
+            })
+            .await
+            .context("Failed to get group's settings")?;
+        let reminders: Vec<u32> = self
+            .pool
+            .conn(move |conn| {
+                let mut stmt = conn.prepare(
+                    "SELECT days FROM group_reminders WHERE group_id = ?1 ORDER BY days DESC",
+                )?;
+                let query = stmt.query_map([group_id.0], |row| row.get(0))?;
+                query.collect()
+            })
+            .await
+            .context("Failed to get group's reminders")?;
+        Ok(GroupSettings {
+            reminders,
+            ..settings.unwrap_or_default()
+        })
+    }
+
+    pub async fn set_group_settings(
+        &self,
+        group_id: ChatId,
+        settings: GroupSettings,
+    ) -> anyhow::Result<()> {
+        let wish_time = settings.wish_time.num_seconds_from_midnight() / 60;
+        let march_first = settings.leap_day == LeapDay::Mar1;
+        let digest: u8 = match settings.digest {
+            Digest::Off => 0,
+            Digest::Weekly => 1,
+            Digest::Monthly => 2,
+        };
+        self.pool
+            .conn_mut(move |conn| {
+                let tx = conn.transaction()?;
+                tx.execute(
+                    "INSERT INTO group_settings (group_id, pin, wish_time, language,
+                    admins_only_add, admins_skip_consent, show_age, march_first, digest, wishes)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+                    ON CONFLICT(group_id) DO UPDATE SET pin = excluded.pin,
+                    wish_time = excluded.wish_time, language = excluded.language,
+                    admins_only_add = excluded.admins_only_add,
+                    admins_skip_consent = excluded.admins_skip_consent,
+                    show_age = excluded.show_age, march_first = excluded.march_first,
+                    digest = excluded.digest, wishes = excluded.wishes",
+                    params![
+                        group_id.0,
+                        settings.pin,
+                        wish_time,
+                        settings.language,
+                        settings.admins_only_add,
+                        settings.admins_skip_consent,
+                        settings.show_age,
+                        march_first,
+                        digest,
+                        settings.wishes
+                    ],
+                )?;
+                tx.execute(
+                    "DELETE FROM group_reminders WHERE group_id = ?1",
+                    [group_id.0],
+                )?;
+                for days in settings.reminders {
+                    tx.execute(
+                        "INSERT OR IGNORE INTO group_reminders (group_id, days) VALUES (?1, ?2)",
+                        params![group_id.0, days],
+                    )?;
+                }
+                tx.commit()
+            })
+            .await
+            .context("Failed to set group's settings")?;
+        Ok(())
     }
 
     pub async fn add_birthday(&self, birthday: Birthday) -> anyhow::Result<bool> {
@@ -112,7 +293,7 @@ COMMIT;",
             .conn(move |conn| {
                 conn.query_row(
                     "SELECT * FROM birthdays WHERE user_id = ?1 AND group_id = ?2",
-                    params![birthday.user_id, birthday.group_id],
+                    params![birthday.user_id.0, birthday.group_id.0],
                     |_| Ok(()),
                 )
                 .optional()
@@ -120,15 +301,12 @@ COMMIT;",
             .await
             .context("Failed to check if user already exists")?;
 
-        if let None = already_exists {
-            let timezone = self
-                .get_timezone(birthday.user_id, birthday.group_id)
-                .await?;
+        if already_exists.is_none() {
             self.pool
                 .conn(move |conn| {
                     conn.execute(
-                        "INSERT INTO birthdays (user_id, group_id, user_lang, year, month, day, timezone) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-                        params![birthday.user_id, birthday.group_id, birthday.user_lang, birthday.year, birthday.month, birthday.day, timezone],
+                        "INSERT INTO birthdays (user_id, group_id, user_lang, year, month, day) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                        params![birthday.user_id.0, birthday.group_id.0, birthday.user_lang, birthday.year, birthday.month, birthday.day],
                     )
                 })
                 .await
@@ -139,28 +317,76 @@ COMMIT;",
         }
     }
 
+    /// Changes the user's birthday in a group, or in every group if `group_id` is `None`.
+    /// Returns false if there was no birthday to change.
+    pub async fn edit_birthday(
+        &self,
+        user_id: UserId,
+        group_id: Option<ChatId>,
+        year: Option<u32>,
+        month: u8,
+        day: u8,
+    ) -> anyhow::Result<bool> {
+        let group_id = group_id.map(|group_id| group_id.0);
+        let edited = self
+            .pool
+            .conn_mut(move |conn| {
+                let tx = conn.transaction()?;
+                // Reminders for the old date shouldn't stop the ones for the new date, unless
+                // that year's birthday was already wished. Wishes are never forgotten, so an
+                // edit can't celebrate the same birthday twice.
+                for table in ["reminder_log", "subscription_log"] {
+                    tx.execute(
+                        &format!(
+                            "DELETE FROM {table}
+                            WHERE user_id = ?1 AND (?2 IS NULL OR group_id = ?2)
+                            AND EXISTS (SELECT * FROM birthdays
+                                WHERE birthdays.user_id = {table}.user_id
+                                AND birthdays.group_id = {table}.group_id
+                                AND (birthdays.month != ?3 OR birthdays.day != ?4))
+                            AND NOT EXISTS (SELECT * FROM wish_log
+                                WHERE wish_log.user_id = {table}.user_id
+                                AND wish_log.group_id = {table}.group_id
+                                AND wish_log.year = {table}.year)"
+                        ),
+                        params![user_id.0, group_id, month, day],
+                    )?;
+                }
+                let edited = tx.execute(
+                    "UPDATE birthdays SET year = ?3, month = ?4, day = ?5
+                    WHERE user_id = ?1 AND (?2 IS NULL OR group_id = ?2)",
+                    params![user_id.0, group_id, year, month, day],
+                )?;
+                tx.commit()?;
+                Ok(edited)
+            })
+            .await
+            .context("Failed to edit birthday")?;
+        Ok(edited > 0)
+    }
+
     pub async fn rm_birthday(&self, action: RemoveBirthday) -> anyhow::Result<()> {
         match action {
-            RemoveBirthday::RemoveGroup(group_id) => self
+            RemoveBirthday::Group(group_id) => self
                 .pool
                 .conn(move |conn| {
-                    conn.execute("DELETE FROM birthdays WHERE group_id = ?1", [group_id])
+                    conn.execute("DELETE FROM birthdays WHERE group_id = ?1", [group_id.0])
                 })
                 .await
                 .context("Failed to remove birthday")?,
-            RemoveBirthday::RemoveUser(user_id) => self
+            RemoveBirthday::User(user_id) => self
                 .pool
                 .conn(move |conn| {
-                    conn.execute("DELETE FROM birthdays WHERE user_id = ?1", [user_id])
+                    conn.execute("DELETE FROM birthdays WHERE user_id = ?1", [user_id.0])
                 })
                 .await
                 .context("Failed to remove birthday")?,
-            RemoveBirthday::RemoveUserInGroup { group_id, user_id } => self
+            RemoveBirthday::UserInGroup { group_id, user_id } => self
                 .pool
                 .conn(move |conn| {
                     conn.execute(
                         "DELETE FROM birthdays WHERE user_id = ?1 AND group_id = ?2",
-                        params![user_id, group_id],
+                        params![user_id.0, group_id.0],
                     )
                 })
                 .await
@@ -169,27 +395,723 @@ COMMIT;",
         Ok(())
     }
 
+    /// Returns the birthdays falling on `date` in the groups using `timezone`
     pub async fn get_birthdays(
         &self,
-        month: u32,
-        day: u32,
+        date: NaiveDate,
+        timezone: String,
     ) -> anyhow::Result<Vec<Option<Birthday>>> {
-        Ok(self.pool.conn(move |conn| { 
-            let mut stmt = conn.prepare("SELECT user_id, group_id, user_lang, year, month, day, timezone FROM birthdays WHERE month = ?1 AND day = ?2")?;
-            let query = stmt.query_map([month, day], |row| Ok(
-                Birthday {
-                    user_id: row.get(0)?,
-                    group_id: row.get(1)?,
-                    user_lang: row.get(2)?,
-                    year: row.get(3)?,
-                    month: row.get(4)?,
-                    day: row.get(5)?,
-                    timezone: row.get(6)?,
+        let (month, day) = (date.month(), date.day());
+        let leap_day: Option<bool> = match (date.leap_year(), month, day) {
+            (false, 2, 28) => Some(false),
+            (false, 3, 1) => Some(true),
+            _ => None,
+        };
+        self.pool
+            .conn(move |conn| {
+                let mut stmt = conn.prepare(
+                    "SELECT user_id, group_id, user_lang, year, month, day FROM birthdays
+                WHERE COALESCE((SELECT timezone FROM group_timezones
+                WHERE group_timezones.group_id = birthdays.group_id), 'UTC') = ?3
+                AND ((month = ?1 AND day = ?2) OR (month = 2 AND day = 29
+                AND ?4 = COALESCE((SELECT march_first FROM group_settings
+                WHERE group_settings.group_id = birthdays.group_id), 0)))",
+                )?;
+                let query = stmt.query_map(params![month, day, timezone, leap_day], birthday)?;
+                Ok(query.map(|b| b.ok()).collect())
+            })
+            .await
+            .context("Failed to get birthdays")
+    }
+
+    pub async fn get_group_birthdays(&self, group_id: ChatId) -> anyhow::Result<Vec<Birthday>> {
+        self.pool
+            .conn(move |conn| {
+                let mut stmt = conn.prepare(
+                    "SELECT user_id, group_id, user_lang, year, month, day
+                    FROM birthdays WHERE group_id = ?1",
+                )?;
+                let query = stmt.query_map([group_id.0], birthday)?;
+                query.collect()
+            })
+            .await
+            .context("Failed to get group's birthdays")
+    }
+
+    pub async fn get_user_birthdays(&self, user_id: UserId) -> anyhow::Result<Vec<Birthday>> {
+        self.pool
+            .conn(move |conn| {
+                let mut stmt = conn.prepare(
+                    "SELECT user_id, group_id, user_lang, year, month, day
+                    FROM birthdays WHERE user_id = ?1",
+                )?;
+                let query = stmt.query_map([user_id.0], birthday)?;
+                query.collect()
+            })
+            .await
+            .context("Failed to get user's birthdays")
+    }
+
+    /// Returns the group's birthdays whose month and day fall between `from` and `to`
+    /// (both included), wrapping around the end of the year.
+    /// Feb 29 birthdays are always returned, since they may be moved in common years.
+    pub async fn get_group_birthdays_between(
+        &self,
+        group_id: ChatId,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> anyhow::Result<Vec<Birthday>> {
+        let (from, to) = if (to - from).num_days() >= 365 {
+            (101, 1231)
+        } else {
+            (from.month() * 100 + from.day(), to.month() * 100 + to.day())
+        };
+        self.pool
+            .conn(move |conn| {
+                let mut stmt = conn.prepare(
+                    "SELECT user_id, group_id, user_lang, year, month, day
+                    FROM birthdays WHERE group_id = ?1 AND (CASE
+                        WHEN ?2 <= ?3 THEN month * 100 + day BETWEEN ?2 AND ?3
+                        ELSE month * 100 + day >= ?2 OR month * 100 + day <= ?3
+                    END OR (month = 2 AND day = 29))",
+                )?;
+                let query = stmt.query_map(params![group_id.0, from, to], birthday)?;
+                query.collect()
+            })
+            .await
+            .context("Failed to get group's birthdays")
+    }
+
+    /// Returns the groups that get a digest of the next birthdays
+    pub async fn get_digest_groups(&self) -> anyhow::Result<Vec<ChatId>> {
+        self.pool
+            .conn(|conn| {
+                let mut stmt =
+                    conn.prepare("SELECT group_id FROM group_settings WHERE digest != 0")?;
+                let query = stmt.query_map([], |row| Ok(ChatId(row.get(0)?)))?;
+                query.collect()
+            })
+            .await
+            .context("Failed to get digest groups")
+    }
+
+    /// Returns the birthdays that the groups using `timezone` must be reminded of on `date`,
+    /// either in the group or privately to its subscribers, with how many days before them
+    pub async fn get_reminders(
+        &self,
+        date: NaiveDate,
+        timezone: String,
+    ) -> anyhow::Result<Vec<(Birthday, u32)>> {
+        let date = date.format("%Y-%m-%d").to_string();
+        self.pool
+            .conn(move |conn| {
+                // Feb 29 birthdays fall on Feb 28 or Mar 1 in common years, like in get_birthdays
+                let mut stmt = conn.prepare(
+                    "WITH due (group_id, days, date) AS (
+                        SELECT group_id, days, date(?1, '+' || days || ' days') FROM (
+                            SELECT group_id, days FROM group_reminders
+                            UNION SELECT group_id, days FROM subscriptions WHERE active
+                        )
+                    )
+                    SELECT user_id, group_id, user_lang, year, month, day, due.days
+                    FROM birthdays JOIN due USING (group_id)
+                    WHERE COALESCE((SELECT timezone FROM group_timezones
+                    WHERE group_timezones.group_id = birthdays.group_id), 'UTC') = ?2
+                    AND ((month = CAST(strftime('%m', due.date) AS INTEGER)
+                    AND day = CAST(strftime('%d', due.date) AS INTEGER))
+                    OR (month = 2 AND day = 29
+                    AND date(strftime('%Y', due.date) || '-02-28', '+1 day') LIKE '%-03-01'
+                    AND strftime('%m-%d', due.date) = CASE COALESCE((SELECT march_first
+                    FROM group_settings WHERE group_settings.group_id = birthdays.group_id), 0)
+                    WHEN 1 THEN '03-01' ELSE '02-28' END))",
+                )?;
+                let query = stmt.query_map(params![date, timezone], |row| {
+                    Ok((birthday(row)?, row.get(6)?))
+                })?;
+                query.collect()
+            })
+            .await
+            .context("Failed to get reminders")
+    }
+
+    /// Returns the timezones of the groups with birthdays
+    pub async fn get_timezones(&self) -> anyhow::Result<Vec<String>> {
+        self.pool
+            .conn(|conn| {
+                let mut stmt = conn.prepare(
+                    "SELECT DISTINCT COALESCE(group_timezones.timezone, 'UTC')
+                    FROM birthdays LEFT JOIN group_timezones USING (group_id)",
+                )?;
+                let query = stmt.query_map([], |row| row.get(0))?;
+                query.collect()
+            })
+            .await
+            .context("Failed to get timezones")
+    }
+
+    /// Stores a birthday until the user confirms it, replacing any older request
+    /// for the same user in the group. Returns the request's id.
+    pub async fn add_pending_birthday(
+        &self,
+        birthday: Birthday,
+        edit: bool,
+        created_at: i64,
+    ) -> anyhow::Result<i64> {
+        self.pool
+            .conn(move |conn| {
+                conn.execute(
+                    "INSERT OR REPLACE INTO pending_birthdays
+                    (user_id, group_id, user_lang, year, month, day, edit, created_at)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                    params![
+                        birthday.user_id.0,
+                        birthday.group_id.0,
+                        birthday.user_lang,
+                        birthday.year,
+                        birthday.month,
+                        birthday.day,
+                        edit,
+                        created_at
+                    ],
+                )?;
+                Ok(conn.last_insert_rowid())
+            })
+            .await
+            .context("Failed to add pending birthday")
+    }
+
+    pub async fn set_pending_message(&self, id: i64, message_id: MessageId) -> anyhow::Result<()> {
+        self.pool
+            .conn(move |conn| {
+                conn.execute(
+                    "UPDATE pending_birthdays SET message_id = ?2 WHERE id = ?1",
+                    params![id, message_id.0],
+                )
+            })
+            .await
+            .context("Failed to set pending birthday's message")?;
+        Ok(())
+    }
+
+    /// Removes the request and returns it, if it was created after `created_after`
+    pub async fn take_pending_birthday(
+        &self,
+        id: i64,
+        created_after: i64,
+    ) -> anyhow::Result<Option<PendingBirthday>> {
+        self.pool
+            .conn(move |conn| {
+                let pending = conn
+                    .query_row(
+                        "SELECT id, user_id, group_id, user_lang, year, month, day, message_id, edit
+                        FROM pending_birthdays WHERE id = ?1 AND created_at > ?2",
+                        params![id, created_after],
+                        pending_birthday,
+                    )
+                    .optional()?;
+                conn.execute("DELETE FROM pending_birthdays WHERE id = ?1", [id])?;
+                Ok(pending)
+            })
+            .await
+            .context("Failed to take pending birthday")
+    }
+
+    /// Returns the user whose confirmation is needed for the request
+    pub async fn get_pending_user(&self, id: i64) -> anyhow::Result<Option<UserId>> {
+        let user_id: Option<u64> = self
+            .pool
+            .conn(move |conn| {
+                conn.query_row(
+                    "SELECT user_id FROM pending_birthdays WHERE id = ?1",
+                    [id],
+                    |row| row.get(0),
+                )
+                .optional()
+            })
+            .await
+            .context("Failed to get pending birthday's user")?;
+        Ok(user_id.map(UserId))
+    }
+
+    /// Removes and returns the requests created before `created_before`
+    pub async fn take_expired_pending_birthdays(
+        &self,
+        created_before: i64,
+    ) -> anyhow::Result<Vec<PendingBirthday>> {
+        self.pool
+            .conn(move |conn| {
+                let mut stmt = conn.prepare(
+                    "SELECT id, user_id, group_id, user_lang, year, month, day, message_id, edit
+                    FROM pending_birthdays WHERE created_at <= ?1",
+                )?;
+                let expired = stmt
+                    .query_map([created_before], pending_birthday)?
+                    .collect::<Result<Vec<_>, _>>()?;
+                conn.execute(
+                    "DELETE FROM pending_birthdays WHERE created_at <= ?1",
+                    [created_before],
+                )?;
+                Ok(expired)
+            })
+            .await
+            .context("Failed to take expired pending birthdays")
+    }
+
+    /// Stores the users' current usernames. Users without a username are forgotten,
+    /// and a username taken by a new user is removed from the old one.
+    pub async fn remember_usernames(
+        &self,
+        users: Vec<(UserId, Option<String>)>,
+    ) -> anyhow::Result<()> {
+        self.pool
+            .conn_mut(move |conn| {
+                let tx = conn.transaction()?;
+                for (user_id, username) in users {
+                    match username {
+                        Some(username) => {
+                            tx.execute(
+                                "DELETE FROM usernames WHERE username = ?2 AND user_id != ?1",
+                                params![user_id.0, username],
+                            )?;
+                            tx.execute(
+                                "INSERT INTO usernames (user_id, username) VALUES (?1, ?2)
+                                ON CONFLICT(user_id) DO UPDATE SET username = excluded.username",
+                                params![user_id.0, username],
+                            )?;
+                        }
+                        None => {
+                            tx.execute("DELETE FROM usernames WHERE user_id = ?1", [user_id.0])?;
+                        }
+                    }
                 }
-            ))?;
-            Ok(query.map(|b| b.ok()).collect())
-        })
-        .await
-        .context("Failed to get birthdays")?)
+                tx.commit()
+            })
+            .await
+            .context("Failed to remember usernames")?;
+        Ok(())
+    }
+
+    pub async fn get_user_id(&self, username: &str) -> anyhow::Result<Option<UserId>> {
+        let username = username.to_string();
+        let user_id: Option<u64> = self
+            .pool
+            .conn(move |conn| {
+                conn.query_row(
+                    "SELECT user_id FROM usernames WHERE username = ?1",
+                    [username],
+                    |row| row.get(0),
+                )
+                .optional()
+            })
+            .await
+            .context("Failed to get user id from username")?;
+        Ok(user_id.map(UserId))
+    }
+
+    pub async fn was_wished(
+        &self,
+        user_id: UserId,
+        group_id: ChatId,
+        year: i32,
+    ) -> anyhow::Result<bool> {
+        let wished: Option<()> = self
+            .pool
+            .conn(move |conn| {
+                conn.query_row(
+                    "SELECT * FROM wish_log WHERE user_id = ?1 AND group_id = ?2 AND year = ?3",
+                    params![user_id.0, group_id.0, year],
+                    |_| Ok(()),
+                )
+                .optional()
+            })
+            .await
+            .context("Failed to check if birthday was already wished")?;
+        Ok(wished.is_some())
+    }
+
+    pub async fn log_wish(
+        &self,
+        user_id: UserId,
+        group_id: ChatId,
+        year: i32,
+    ) -> anyhow::Result<()> {
+        self.pool
+            .conn(move |conn| {
+                conn.execute(
+                    "INSERT OR IGNORE INTO wish_log (user_id, group_id, year) VALUES (?1, ?2, ?3)",
+                    params![user_id.0, group_id.0, year],
+                )
+            })
+            .await
+            .context("Failed to log wish")?;
+        Ok(())
+    }
+
+    pub async fn was_reminded(
+        &self,
+        user_id: UserId,
+        group_id: ChatId,
+        year: i32,
+        days: u32,
+    ) -> anyhow::Result<bool> {
+        let reminded: Option<()> = self
+            .pool
+            .conn(move |conn| {
+                conn.query_row(
+                    "SELECT * FROM reminder_log
+                    WHERE user_id = ?1 AND group_id = ?2 AND year = ?3 AND days = ?4",
+                    params![user_id.0, group_id.0, year, days],
+                    |_| Ok(()),
+                )
+                .optional()
+            })
+            .await
+            .context("Failed to check if birthday was already reminded")?;
+        Ok(reminded.is_some())
+    }
+
+    pub async fn log_reminder(
+        &self,
+        user_id: UserId,
+        group_id: ChatId,
+        year: i32,
+        days: u32,
+    ) -> anyhow::Result<()> {
+        self.pool
+            .conn(move |conn| {
+                conn.execute(
+                    "INSERT OR IGNORE INTO reminder_log (user_id, group_id, year, days)
+                    VALUES (?1, ?2, ?3, ?4)",
+                    params![user_id.0, group_id.0, year, days],
+                )
+            })
+            .await
+            .context("Failed to log reminder")?;
+        Ok(())
+    }
+
+    /// Subscribes the user to private reminders of the group's birthdays,
+    /// `days` before each of them
+    pub async fn subscribe(
+        &self,
+        user_id: UserId,
+        group_id: ChatId,
+        user_lang: String,
+        days: u32,
+    ) -> anyhow::Result<()> {
+        self.pool
+            .conn(move |conn| {
+                conn.execute(
+                    "INSERT INTO subscriptions (user_id, group_id, user_lang, days, active)
+                    VALUES (?1, ?2, ?3, ?4, 1) ON CONFLICT(user_id, group_id) DO UPDATE SET
+                    user_lang = excluded.user_lang, days = excluded.days, active = 1",
+                    params![user_id.0, group_id.0, user_lang, days],
+                )
+            })
+            .await
+            .context("Failed to subscribe")?;
+        Ok(())
+    }
+
+    /// Returns whether the user was subscribed
+    pub async fn unsubscribe(&self, user_id: UserId, group_id: ChatId) -> anyhow::Result<bool> {
+        let removed = self
+            .pool
+            .conn(move |conn| {
+                conn.execute(
+                    "DELETE FROM subscriptions WHERE user_id = ?1 AND group_id = ?2",
+                    params![user_id.0, group_id.0],
+                )
+            })
+            .await
+            .context("Failed to unsubscribe")?;
+        Ok(removed > 0)
+    }
+
+    /// Stops the reminders of a user who can't get private messages
+    pub async fn disable_subscription(
+        &self,
+        user_id: UserId,
+        group_id: ChatId,
+    ) -> anyhow::Result<()> {
+        self.pool
+            .conn(move |conn| {
+                conn.execute(
+                    "UPDATE subscriptions SET active = 0 WHERE user_id = ?1 AND group_id = ?2",
+                    params![user_id.0, group_id.0],
+                )
+            })
+            .await
+            .context("Failed to disable subscription")?;
+        Ok(())
+    }
+
+    /// Returns the users who want to be reminded of the group's birthdays
+    /// `days` before, with their language
+    pub async fn get_subscribers(
+        &self,
+        group_id: ChatId,
+        days: u32,
+    ) -> anyhow::Result<Vec<(UserId, String)>> {
+        self.pool
+            .conn(move |conn| {
+                let mut stmt = conn.prepare(
+                    "SELECT user_id, user_lang FROM subscriptions
+                    WHERE group_id = ?1 AND days = ?2 AND active",
+                )?;
+                let query = stmt.query_map(params![group_id.0, days], |row| {
+                    Ok((UserId(row.get(0)?), row.get(1)?))
+                })?;
+                query.collect()
+            })
+            .await
+            .context("Failed to get subscribers")
+    }
+
+    pub async fn was_subscriber_reminded(
+        &self,
+        subscriber_id: UserId,
+        user_id: UserId,
+        group_id: ChatId,
+        year: i32,
+    ) -> anyhow::Result<bool> {
+        let reminded: Option<()> = self
+            .pool
+            .conn(move |conn| {
+                conn.query_row(
+                    "SELECT * FROM subscription_log WHERE subscriber_id = ?1
+                    AND user_id = ?2 AND group_id = ?3 AND year = ?4",
+                    params![subscriber_id.0, user_id.0, group_id.0, year],
+                    |_| Ok(()),
+                )
+                .optional()
+            })
+            .await
+            .context("Failed to check if subscriber was already reminded")?;
+        Ok(reminded.is_some())
+    }
+
+    pub async fn log_subscriber_reminder(
+        &self,
+        subscriber_id: UserId,
+        user_id: UserId,
+        group_id: ChatId,
+        year: i32,
+    ) -> anyhow::Result<()> {
+        self.pool
+            .conn(move |conn| {
+                conn.execute(
+                    "INSERT OR IGNORE INTO subscription_log (subscriber_id, user_id, group_id, year)
+                    VALUES (?1, ?2, ?3, ?4)",
+                    params![subscriber_id.0, user_id.0, group_id.0, year],
+                )
+            })
+            .await
+            .context("Failed to log subscriber reminder")?;
+        Ok(())
+    }
+
+    pub async fn was_digest_sent(&self, group_id: ChatId, date: NaiveDate) -> anyhow::Result<bool> {
+        let sent: Option<()> = self
+            .pool
+            .conn(move |conn| {
+                conn.query_row(
+                    "SELECT * FROM digest_log WHERE group_id = ?1 AND date = ?2",
+                    params![group_id.0, date.to_string()],
+                    |_| Ok(()),
+                )
+                .optional()
+            })
+            .await
+            .context("Failed to check if digest was already sent")?;
+        Ok(sent.is_some())
+    }
+
+    pub async fn log_digest(&self, group_id: ChatId, date: NaiveDate) -> anyhow::Result<()> {
+        self.pool
+            .conn(move |conn| {
+                conn.execute(
+                    "INSERT OR IGNORE INTO digest_log (group_id, date) VALUES (?1, ?2)",
+                    params![group_id.0, date.to_string()],
+                )
+            })
+            .await
+            .context("Failed to log digest")?;
+        Ok(())
+    }
+}
+
+/// Reads a birthday selected as `user_id, group_id, user_lang, year, month, day`
+fn birthday(row: &Row) -> rusqlite::Result<Birthday> {
+    Ok(Birthday {
+        user_id: UserId(row.get(0)?),
+        group_id: ChatId(row.get(1)?),
+        user_lang: row.get(2)?,
+        year: row.get(3)?,
+        month: row.get(4)?,
+        day: row.get(5)?,
+    })
+}
+
+fn pending_birthday(row: &Row) -> rusqlite::Result<PendingBirthday> {
+    Ok(PendingBirthday {
+        id: row.get(0)?,
+        birthday: Birthday {
+            user_id: UserId(row.get(1)?),
+            group_id: ChatId(row.get(2)?),
+            user_lang: row.get(3)?,
+            year: row.get(4)?,
+            month: row.get(5)?,
+            day: row.get(6)?,
+        },
+        message_id: row.get::<_, Option<i32>>(7)?.map(MessageId),
+        edit: row.get(8)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GROUP: ChatId = ChatId(-1);
+    const OTHER_GROUP: ChatId = ChatId(-2);
+
+    async fn in_memory() -> Database {
+        // Every connection would open its own in-memory database
+        let pool = PoolBuilder::new()
+            .path(":memory:")
+            .num_conns(1)
+            .open()
+            .await
+            .unwrap();
+        migrations::migrate(&pool).await.unwrap();
+        Database { pool }
+    }
+
+    async fn add(db: &Database, user_id: u64, group_id: ChatId, month: u8, day: u8) {
+        let birthday = Birthday {
+            user_id: UserId(user_id),
+            group_id,
+            user_lang: "en".to_string(),
+            year: None,
+            month,
+            day,
+        };
+        assert!(db.add_birthday(birthday).await.unwrap());
+    }
+
+    async fn set_reminders(db: &Database, group_id: ChatId, leap_day: LeapDay, reminders: &[u32]) {
+        let settings = GroupSettings {
+            leap_day,
+            reminders: reminders.to_vec(),
+            ..Default::default()
+        };
+        db.set_group_settings(group_id, settings).await.unwrap();
+    }
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    async fn users_between(db: &Database, from: NaiveDate, to: NaiveDate) -> Vec<u64> {
+        let birthdays = db.get_group_birthdays_between(GROUP, from, to).await;
+        let mut users: Vec<u64> = birthdays
+            .unwrap()
+            .iter()
+            .map(|birthday| birthday.user_id.0)
+            .collect();
+        users.sort();
+        users
+    }
+
+    async fn reminders(db: &Database, date: NaiveDate) -> Vec<(u64, i64, u32)> {
+        let mut reminders: Vec<(u64, i64, u32)> = db
+            .get_reminders(date, "UTC".to_string())
+            .await
+            .unwrap()
+            .iter()
+            .map(|(birthday, days)| (birthday.user_id.0, birthday.group_id.0, *days))
+            .collect();
+        reminders.sort();
+        reminders
+    }
+
+    #[tokio::test]
+    async fn birthdays_between_wrap_around_new_year() {
+        let db = in_memory().await;
+        add(&db, 1, GROUP, 12, 30).await;
+        add(&db, 2, GROUP, 1, 2).await;
+        add(&db, 3, GROUP, 6, 15).await;
+        add(&db, 4, GROUP, 1, 10).await;
+        add(&db, 5, OTHER_GROUP, 12, 31).await;
+        assert_eq!(
+            users_between(&db, date(2026, 12, 28), date(2027, 1, 3)).await,
+            vec![1, 2]
+        );
+        assert_eq!(
+            users_between(&db, date(2026, 6, 1), date(2026, 6, 30)).await,
+            vec![3]
+        );
+        assert_eq!(
+            users_between(&db, date(2026, 1, 5), date(2027, 1, 5)).await,
+            vec![1, 2, 3, 4]
+        );
+    }
+
+    #[tokio::test]
+    async fn birthdays_between_include_leap_days() {
+        let db = in_memory().await;
+        add(&db, 1, GROUP, 2, 29).await;
+        // Callers decide if Feb 29 falls in the range, depending on the group's leap day
+        assert_eq!(
+            users_between(&db, date(2027, 3, 1), date(2027, 3, 7)).await,
+            vec![1]
+        );
+        assert_eq!(
+            users_between(&db, date(2027, 2, 1), date(2027, 2, 28)).await,
+            vec![1]
+        );
+    }
+
+    #[tokio::test]
+    async fn reminds_leap_days_on_the_group_leap_day() {
+        let db = in_memory().await;
+        add(&db, 1, GROUP, 2, 29).await;
+        add(&db, 2, OTHER_GROUP, 2, 29).await;
+        set_reminders(&db, GROUP, LeapDay::Feb28, &[1]).await;
+        set_reminders(&db, OTHER_GROUP, LeapDay::Mar1, &[1]).await;
+        assert_eq!(reminders(&db, date(2027, 2, 27)).await, vec![(1, -1, 1)]);
+        assert_eq!(reminders(&db, date(2027, 2, 28)).await, vec![(2, -2, 1)]);
+        assert_eq!(
+            reminders(&db, date(2028, 2, 28)).await,
+            vec![(1, -1, 1), (2, -2, 1)]
+        );
+        assert_eq!(reminders(&db, date(2028, 2, 29)).await, vec![]);
+    }
+
+    #[tokio::test]
+    async fn reminds_groups_and_subscribers() {
+        let db = in_memory().await;
+        add(&db, 1, GROUP, 10, 25).await;
+        add(&db, 2, OTHER_GROUP, 10, 25).await;
+        set_reminders(&db, GROUP, LeapDay::Feb28, &[1, 7]).await;
+        set_reminders(&db, OTHER_GROUP, LeapDay::Feb28, &[7]).await;
+        db.set_group_timezone(OTHER_GROUP, Tz::Europe__Rome)
+            .await
+            .unwrap();
+        db.subscribe(UserId(3), GROUP, "en".to_string(), 3)
+            .await
+            .unwrap();
+        db.subscribe(UserId(4), GROUP, "en".to_string(), 7)
+            .await
+            .unwrap();
+        // The group and the subscriber reminded 7 days before get a single reminder
+        assert_eq!(reminders(&db, date(2026, 10, 18)).await, vec![(1, -1, 7)]);
+        assert_eq!(reminders(&db, date(2026, 10, 22)).await, vec![(1, -1, 3)]);
+        assert_eq!(reminders(&db, date(2026, 10, 24)).await, vec![(1, -1, 1)]);
+        assert_eq!(reminders(&db, date(2026, 10, 25)).await, vec![]);
+        add(&db, 5, GROUP, 1, 2).await;
+        assert_eq!(reminders(&db, date(2026, 12, 26)).await, vec![(5, -1, 7)]);
+        assert_eq!(reminders(&db, date(2026, 12, 30)).await, vec![(5, -1, 3)]);
     }
 }
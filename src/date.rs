@@ -0,0 +1,223 @@
+// Copyright: https://github.com/hex0x0000/BirthdayBot/src/branch/master/LICENSE
+use chrono::{Datelike, NaiveDate, Utc};
+
+/// Used to validate day and month when the year of birth isn't known, so that Feb 29 is allowed
+const LEAP_YEAR: i32 = 2000;
+pub const MIN_YEAR: i32 = 1900;
+
+#[derive(Debug)]
+pub enum DateError {
+    Format,
+    Year,
+    Month,
+    Day,
+}
+
+impl DateError {
+    /// Label explaining which part of the date is wrong
+    pub fn label(&self) -> &'static str {
+        match self {
+            DateError::Format => "ERR_INVALID_DATE",
+            DateError::Year => "ERR_INVALID_YEAR",
+            DateError::Month => "ERR_INVALID_MONTH",
+            DateError::Day => "ERR_INVALID_DAY",
+        }
+    }
+}
+
+/// Parses a birthday written in one of these formats (the year is always optional):
+/// - `2000/01/31` and `--/01/31`
+/// - `2000-01-31` and `--01-31` (ISO 8601)
+/// - `31/01/2000`, `31/01`, `31.01.2000` and `31.01`
+/// - `31 January 2000`, `January 31` and abbreviations like `31 jan`
+///
+/// In numeric dates the day comes before the month, unless the year (or `--` in its place)
+/// comes first.
+///
+/// `month_names` contains the space-separated names of the months from January to December,
+/// for every language that should be understood.
+pub fn parse_date(date: &str, month_names: &[String]) -> Result<(Option<u32>, u8, u8), DateError> {
+    let date = date.trim().to_lowercase();
+    let (year, month, day) = if date.chars().any(char::is_alphabetic) {
+        parse_with_month_name(&date, month_names)?
+    } else {
+        parse_numeric(&date)?
+    };
+    let year: Option<u32> = match year {
+        Some(year) => Some(year.parse().map_err(|_| DateError::Year)?),
+        None => None,
+    };
+    let day: u8 = day.parse().map_err(|_| DateError::Day)?;
+    if let Some(year) = year {
+        if !(MIN_YEAR..=Utc::now().year()).contains(&(year as i32)) {
+            return Err(DateError::Year);
+        }
+    }
+    if !(1..=12).contains(&month) {
+        return Err(DateError::Month);
+    }
+    if !is_valid(year, month, day) {
+        return Err(DateError::Day);
+    }
+    Ok((year, month, day))
+}
+
+/// Checks that the date exists, allowing Feb 29 when the year isn't known
+pub fn is_valid(year: Option<u32>, month: u8, day: u8) -> bool {
+    let year = year.map(|year| year as i32).unwrap_or(LEAP_YEAR);
+    NaiveDate::from_ymd_opt(year, month.into(), day.into()).is_some()
+}
+
+/// Splits a numeric date into its year, month and day
+fn parse_numeric(date: &str) -> Result<(Option<&str>, u8, &str), DateError> {
+    // Dates without the year, both in ISO 8601 (--MM-DD) and in the bot's format (--/MM/DD)
+    if let Some(date) = date.strip_prefix("--/").or_else(|| date.strip_prefix("--")) {
+        let (month, day) = date.split_once(['/', '-']).ok_or(DateError::Format)?;
+        return Ok((None, parse_month(month)?, day));
+    }
+    let separator = date
+        .chars()
+        .find(|c| ['/', '-', '.'].contains(c))
+        .ok_or(DateError::Format)?;
+    if date.chars().any(|c| !c.is_ascii_digit() && c != separator) {
+        return Err(DateError::Format);
+    }
+    let list: Vec<&str> = date.split(separator).collect();
+    match list[..] {
+        [day, month] if separator != '-' => Ok((None, parse_month(month)?, day)),
+        [year, month, day] if separator != '.' && year.len() == 4 => {
+            Ok((Some(year), parse_month(month)?, day))
+        }
+        [day, month, year] if separator != '-' && year.len() == 4 => {
+            Ok((Some(year), parse_month(month)?, day))
+        }
+        _ => Err(DateError::Format),
+    }
+}
+
+fn parse_month(month: &str) -> Result<u8, DateError> {
+    month.parse().map_err(|_| DateError::Month)
+}
+
+/// Splits a date like `31 January 2000` or `January 31` into its year, month and day
+fn parse_with_month_name<'a>(
+    date: &'a str,
+    month_names: &[String],
+) -> Result<(Option<&'a str>, u8, &'a str), DateError> {
+    let mut month = None;
+    let mut numbers = vec![];
+    for word in date
+        .split(|c: char| c.is_whitespace() || c == ',' || c == '.')
+        .filter(|word| !word.is_empty())
+    {
+        if word.chars().all(|c| c.is_ascii_digit()) {
+            numbers.push(word);
+        } else if month.is_none() {
+            month = Some(month_number(word, month_names).ok_or(DateError::Month)?);
+        } else {
+            return Err(DateError::Format);
+        }
+    }
+    let month = month.ok_or(DateError::Month)?;
+    match numbers[..] {
+        [day] => Ok((None, month, day)),
+        [day, year] if year.len() == 4 => Ok((Some(year), month, day)),
+        [year, day] if year.len() == 4 => Ok((Some(year), month, day)),
+        _ => Err(DateError::Format),
+    }
+}
+
+/// Finds the month with the given name, abbreviations of at least 3 letters are accepted
+fn month_number(word: &str, month_names: &[String]) -> Option<u8> {
+    if word.chars().count() < 3 {
+        return None;
+    }
+    month_names.iter().find_map(|names| {
+        names
+            .split_whitespace()
+            .position(|name| name.to_lowercase().starts_with(word))
+            .map(|month| month as u8 + 1)
+    })
+}
+
+/// Returns the number of days in the month, counting Feb 29 when the year isn't known
+pub fn days_in_month(year: Option<u32>, month: u8) -> u8 {
+    let year = year.map(|year| year as i32).unwrap_or(LEAP_YEAR);
+    (28..=31)
+        .rev()
+        .find(|day| NaiveDate::from_ymd_opt(year, month.into(), *day).is_some())
+        .unwrap_or(28) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(date: &str) -> Result<(Option<u32>, u8, u8), DateError> {
+        parse_date(
+            date,
+            &[
+                "Gennaio Febbraio Marzo Aprile Maggio Giugno Luglio Agosto Settembre Ottobre Novembre Dicembre".to_string(),
+                "January February March April May June July August September October November December".to_string(),
+            ],
+        )
+    }
+
+    #[test]
+    fn day_comes_before_month() {
+        assert!(matches!(parse("31/01/2000"), Ok((Some(2000), 1, 31))));
+        assert!(matches!(parse("31/01"), Ok((None, 1, 31))));
+        assert!(matches!(parse("31.01.2000"), Ok((Some(2000), 1, 31))));
+        assert!(matches!(parse("31.01"), Ok((None, 1, 31))));
+        assert!(matches!(parse("05/03"), Ok((None, 3, 5))));
+        assert!(matches!(parse("05/03/2000"), Ok((Some(2000), 3, 5))));
+        assert!(matches!(parse("05.03"), Ok((None, 3, 5))));
+        assert!(matches!(parse("01/31"), Err(DateError::Month)));
+        assert!(matches!(parse("01/31/2000"), Err(DateError::Month)));
+    }
+
+    #[test]
+    fn month_comes_before_day_after_the_year() {
+        assert!(matches!(parse("2000/01/31"), Ok((Some(2000), 1, 31))));
+        assert!(matches!(parse("--/01/31"), Ok((None, 1, 31))));
+        assert!(matches!(parse("2000/05/03"), Ok((Some(2000), 5, 3))));
+        assert!(matches!(parse("2000.01.31"), Err(DateError::Format)));
+    }
+
+    #[test]
+    fn iso_8601() {
+        assert!(matches!(parse("2000-01-31"), Ok((Some(2000), 1, 31))));
+        assert!(matches!(parse("--01-31"), Ok((None, 1, 31))));
+        assert!(matches!(parse("31-01-2000"), Err(DateError::Format)));
+    }
+
+    #[test]
+    fn month_names() {
+        assert!(matches!(parse("31 January 2000"), Ok((Some(2000), 1, 31))));
+        assert!(matches!(parse("January 31, 2000"), Ok((Some(2000), 1, 31))));
+        assert!(matches!(parse("31 gennaio"), Ok((None, 1, 31))));
+        assert!(matches!(parse("31 jan"), Ok((None, 1, 31))));
+        assert!(matches!(parse("31 ja"), Err(DateError::Month)));
+        assert!(matches!(parse("31 january march"), Err(DateError::Format)));
+    }
+
+    #[test]
+    fn calendar_validation() {
+        assert!(matches!(parse("29/02"), Ok((None, 2, 29))));
+        assert!(matches!(parse("2000/02/29"), Ok((Some(2000), 2, 29))));
+        assert!(matches!(parse("2001/02/29"), Err(DateError::Day)));
+        assert!(matches!(parse("2000/02/31"), Err(DateError::Day)));
+        assert!(matches!(parse("2000/13/01"), Err(DateError::Month)));
+        assert!(matches!(parse("1800/01/01"), Err(DateError::Year)));
+        assert!(matches!(parse("9999/01/01"), Err(DateError::Year)));
+    }
+
+    #[test]
+    fn malformed_input() {
+        assert!(matches!(parse(""), Err(DateError::Format)));
+        assert!(matches!(parse("2000/01"), Err(DateError::Day)));
+        assert!(matches!(parse("2000/01/31/1"), Err(DateError::Format)));
+        assert!(matches!(parse("01/31-2000"), Err(DateError::Format)));
+        assert!(matches!(parse("00/01/31"), Err(DateError::Format)));
+    }
+}
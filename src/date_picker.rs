@@ -0,0 +1,307 @@
+// Copyright: https://github.com/hex0x0000/BirthdayBot/src/branch/master/LICENSE
+use crate::database::{Birthday, Database};
+use crate::date::{days_in_month, is_valid, MIN_YEAR};
+use crate::lang::Langs;
+use crate::settings;
+use crate::Bot;
+use anyhow::Context;
+use chrono::{Datelike, Utc};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use teloxide::{
+    prelude::*,
+    types::{InlineKeyboardButton, InlineKeyboardMarkup, MessageId},
+    ApiError, RequestError,
+};
+
+const YEARS_PER_PAGE: i32 = 20;
+const YEARS_PER_ROW: usize = 5;
+const MONTHS_PER_ROW: usize = 3;
+const DAYS_PER_ROW: usize = 7;
+/// Pickers left untouched for this long are forgotten
+const PICKER_EXPIRY: Duration = Duration::from_secs(60 * 60);
+
+/// Step of the date picker the user is at
+#[derive(Clone, Copy)]
+enum Step {
+    Year,
+    Month { year: Option<u32> },
+    Day { year: Option<u32>, month: u8 },
+}
+
+struct Picker {
+    message_id: MessageId,
+    step: Step,
+    updated_at: Instant,
+}
+
+/// Open date pickers, one per user in every group
+#[derive(Default)]
+pub struct DatePickers(Mutex<HashMap<(ChatId, UserId), Picker>>);
+
+impl DatePickers {
+    fn get(&self, group_id: ChatId, user_id: UserId, message_id: MessageId) -> Option<Step> {
+        let pickers = self.0.lock().expect("Date pickers lock poisoned");
+        match pickers.get(&(group_id, user_id)) {
+            Some(picker)
+                if picker.message_id == message_id
+                    && picker.updated_at.elapsed() < PICKER_EXPIRY =>
+            {
+                Some(picker.step)
+            }
+            _ => None,
+        }
+    }
+
+    /// Stores the user's picker and forgets the expired ones. Returns the message of the
+    /// picker it replaced, if it was a different one.
+    fn set(
+        &self,
+        group_id: ChatId,
+        user_id: UserId,
+        message_id: MessageId,
+        step: Step,
+    ) -> Option<MessageId> {
+        let mut pickers = self.0.lock().expect("Date pickers lock poisoned");
+        pickers.retain(|_, picker| picker.updated_at.elapsed() < PICKER_EXPIRY);
+        let picker = Picker {
+            message_id,
+            step,
+            updated_at: Instant::now(),
+        };
+        pickers
+            .insert((group_id, user_id), picker)
+            .map(|old| old.message_id)
+            .filter(|old_id| *old_id != message_id)
+    }
+
+    fn remove(&self, group_id: ChatId, user_id: UserId) {
+        self.0
+            .lock()
+            .expect("Date pickers lock poisoned")
+            .remove(&(group_id, user_id));
+    }
+}
+
+/// Keyboard with `YEARS_PER_PAGE` years ending with `last_year`
+fn years_keyboard(labels: &Langs, lang: &str, last_year: i32) -> InlineKeyboardMarkup {
+    let current_year = Utc::now().year();
+    let first_year = (last_year - YEARS_PER_PAGE + 1).max(MIN_YEAR);
+    let years: Vec<InlineKeyboardButton> = (first_year..=last_year)
+        .map(|year| {
+            InlineKeyboardButton::callback(year.to_string(), format!("picker year {}", year))
+        })
+        .collect();
+    let mut keyboard: Vec<Vec<InlineKeyboardButton>> = years
+        .chunks(YEARS_PER_ROW)
+        .map(|row| row.to_vec())
+        .collect();
+    let mut navigation = vec![];
+    if first_year > MIN_YEAR {
+        navigation.push(InlineKeyboardButton::callback(
+            "⬅️",
+            format!("picker years {}", first_year - 1),
+        ));
+    }
+    if last_year < current_year {
+        navigation.push(InlineKeyboardButton::callback(
+            "➡️",
+            format!(
+                "picker years {}",
+                (last_year + YEARS_PER_PAGE).min(current_year)
+            ),
+        ));
+    }
+    if !navigation.is_empty() {
+        keyboard.push(navigation);
+    }
+    keyboard.push(vec![
+        InlineKeyboardButton::callback(labels.get(lang, "PICKER_SKIP_YEAR"), "picker noyear"),
+        InlineKeyboardButton::callback(labels.get(lang, "PICKER_CANCEL"), "picker cancel"),
+    ]);
+    InlineKeyboardMarkup::new(keyboard)
+}
+
+fn months_keyboard(labels: &Langs, lang: &str) -> InlineKeyboardMarkup {
+    let months: Vec<InlineKeyboardButton> = labels
+        .get(lang, "MONTHS")
+        .split_whitespace()
+        .enumerate()
+        .map(|(month, name)| {
+            InlineKeyboardButton::callback(name, format!("picker month {}", month + 1))
+        })
+        .collect();
+    let mut keyboard: Vec<Vec<InlineKeyboardButton>> = months
+        .chunks(MONTHS_PER_ROW)
+        .map(|row| row.to_vec())
+        .collect();
+    keyboard.push(vec![InlineKeyboardButton::callback(
+        labels.get(lang, "PICKER_CANCEL"),
+        "picker cancel",
+    )]);
+    InlineKeyboardMarkup::new(keyboard)
+}
+
+fn days_keyboard(labels: &Langs, lang: &str, year: Option<u32>, month: u8) -> InlineKeyboardMarkup {
+    let days: Vec<InlineKeyboardButton> = (1..=days_in_month(year, month))
+        .map(|day| InlineKeyboardButton::callback(day.to_string(), format!("picker day {}", day)))
+        .collect();
+    let mut keyboard: Vec<Vec<InlineKeyboardButton>> =
+        days.chunks(DAYS_PER_ROW).map(|row| row.to_vec()).collect();
+    keyboard.push(vec![InlineKeyboardButton::callback(
+        labels.get(lang, "PICKER_CANCEL"),
+        "picker cancel",
+    )]);
+    InlineKeyboardMarkup::new(keyboard)
+}
+
+/// Sends the date picker to add the user's birthday in the group
+pub async fn start(
+    bot: &Bot,
+    labels: &Langs,
+    pickers: &DatePickers,
+    group_id: ChatId,
+    user_id: UserId,
+    lang: &str,
+) -> anyhow::Result<()> {
+    let message = bot
+        .send_message(group_id, labels.get(lang, "PICKER_YEAR"))
+        .reply_markup(years_keyboard(labels, lang, Utc::now().year()))
+        .await
+        .context("Failed to send PICKER_YEAR")?;
+    // Only the newest picker can be used, so the old one is closed
+    if let Some(old_id) = pickers.set(group_id, user_id, message.id, Step::Year) {
+        match bot
+            .edit_message_text(group_id, old_id, labels.get(lang, "PICKER_CANCELLED"))
+            .await
+        {
+            Ok(_) | Err(RequestError::Api(ApiError::MessageNotModified)) => {}
+            Err(err) => log::error!("Failed to close the old date picker: {:?}", err),
+        }
+    }
+    Ok(())
+}
+
+/// Edits the picker's message, ignoring clicks that didn't change it
+async fn edit(
+    bot: &Bot,
+    message: &Message,
+    text: String,
+    keyboard: Option<InlineKeyboardMarkup>,
+) -> anyhow::Result<()> {
+    let request = bot.edit_message_text(message.chat.id, message.id, text);
+    let result = match keyboard {
+        Some(keyboard) => request.reply_markup(keyboard).await,
+        None => request.await,
+    };
+    match result {
+        Ok(_) | Err(RequestError::Api(ApiError::MessageNotModified)) => Ok(()),
+        Err(err) => Err(err).context("Failed to edit date picker"),
+    }
+}
+
+/// Handles a click on the date picker. Returns the label to show to the user
+/// if the picker can't be used by them.
+pub async fn answer(
+    bot: &Bot,
+    db: &Database,
+    labels: &Langs,
+    pickers: &DatePickers,
+    query: &CallbackQuery,
+    data: &str,
+    lang: &str,
+) -> anyhow::Result<Option<&'static str>> {
+    let message = query
+        .message
+        .as_ref()
+        .context("Date picker without message")?;
+    let (group_id, user_id) = (message.chat.id, query.from.id);
+    let step = match pickers.get(group_id, user_id, message.id) {
+        Some(step) => step,
+        None => return Ok(Some("PICKER_NOT_YOURS")),
+    };
+    let (action, value) = data.split_once(' ').unwrap_or((data, ""));
+    match (step, action) {
+        (_, "cancel") => {
+            pickers.remove(group_id, user_id);
+            edit(bot, message, labels.get(lang, "PICKER_CANCELLED"), None).await?;
+        }
+        (Step::Year, "years") => {
+            let last_year: i32 = value
+                .parse()
+                .ok()
+                .filter(|year| (MIN_YEAR..=Utc::now().year()).contains(year))
+                .context("Invalid picker year page")?;
+            edit(
+                bot,
+                message,
+                labels.get(lang, "PICKER_YEAR"),
+                Some(years_keyboard(labels, lang, last_year)),
+            )
+            .await?;
+        }
+        (Step::Year, "year" | "noyear") => {
+            let year: Option<u32> = match action {
+                "year" => Some(
+                    value
+                        .parse()
+                        .ok()
+                        .filter(|year| (MIN_YEAR..=Utc::now().year()).contains(&(*year as i32)))
+                        .context("Invalid picker year")?,
+                ),
+                _ => None,
+            };
+            pickers.set(group_id, user_id, message.id, Step::Month { year });
+            edit(
+                bot,
+                message,
+                labels.get(lang, "PICKER_MONTH"),
+                Some(months_keyboard(labels, lang)),
+            )
+            .await?;
+        }
+        (Step::Month { year }, "month") => {
+            let month: u8 = value
+                .parse()
+                .ok()
+                .filter(|month| (1..=12).contains(month))
+                .context("Invalid picker month")?;
+            pickers.set(group_id, user_id, message.id, Step::Day { year, month });
+            edit(
+                bot,
+                message,
+                labels.get(lang, "PICKER_DAY"),
+                Some(days_keyboard(labels, lang, year, month)),
+            )
+            .await?;
+        }
+        (Step::Day { year, month }, "day") => {
+            let day: u8 = value
+                .parse()
+                .ok()
+                .filter(|day| is_valid(year, month, *day))
+                .context("Invalid picker day")?;
+            pickers.remove(group_id, user_id);
+            let was_added = db
+                .add_birthday(Birthday {
+                    user_id,
+                    group_id,
+                    user_lang: settings::user_lang(Some(&query.from)),
+                    year,
+                    month,
+                    day,
+                })
+                .await?;
+            let label = if was_added {
+                "BIRTHDAY_ADD_SUCCESS"
+            } else {
+                "BIRTHDAY_EXISTS"
+            };
+            edit(bot, message, labels.get(lang, label), None).await?;
+        }
+        // Clicks on buttons of a previous step
+        _ => {}
+    }
+    Ok(None)
+}
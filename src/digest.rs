@@ -0,0 +1,120 @@
+// Copyright: https://github.com/hex0x0000/BirthdayBot/src/branch/master/LICENSE
+use crate::commands::{birthday_entries, split_messages};
+use crate::database::{Birthday, Database, Digest};
+use crate::date::days_in_month;
+use crate::is_due;
+use crate::lang::Langs;
+use crate::Bot;
+use anyhow::Context;
+use chrono::{DateTime, Datelike, Days, NaiveDate, Utc, Weekday};
+use chrono_tz::Tz;
+use std::collections::HashMap;
+use teloxide::prelude::*;
+
+/// Posts the digests of the groups whose week or month started between the last check
+/// and now, at the group's wish time in the group's timezone
+pub async fn post_digests(
+    bot: &Bot,
+    db: &Database,
+    labels: &Langs,
+    last_check: DateTime<Utc>,
+    now: DateTime<Utc>,
+) -> anyhow::Result<()> {
+    for group_id in db.get_digest_groups().await? {
+        if let Err(err) = post_digest(bot, db, labels, group_id, last_check, now).await {
+            log::error!("Failed to post {}'s digest: {}", group_id, err);
+            log::error!("Root cause: {}", err.root_cause());
+        }
+    }
+    Ok(())
+}
+
+async fn post_digest(
+    bot: &Bot,
+    db: &Database,
+    labels: &Langs,
+    group_id: ChatId,
+    last_check: DateTime<Utc>,
+    now: DateTime<Utc>,
+) -> anyhow::Result<()> {
+    let settings = db.get_group_settings(group_id).await?;
+    let tz: Tz = db
+        .get_group_timezone(group_id)
+        .await?
+        .parse()
+        .unwrap_or(Tz::UTC);
+    let mut day = last_check
+        .with_timezone(&tz)
+        .date_naive()
+        .pred_opt()
+        .context("Failed to get previous day")?;
+    let today = now.with_timezone(&tz).date_naive();
+    while day <= today {
+        let (label, last_day) = match settings.digest {
+            Digest::Weekly if day.weekday() == Weekday::Mon => (
+                "DIGEST_WEEKLY",
+                day.checked_add_days(Days::new(6))
+                    .context("Failed to get last day")?,
+            ),
+            Digest::Monthly if day.day() == 1 => (
+                "DIGEST_MONTHLY",
+                NaiveDate::from_ymd_opt(
+                    day.year(),
+                    day.month(),
+                    days_in_month(Some(day.year() as u32), day.month() as u8).into(),
+                )
+                .context("Failed to get last day")?,
+            ),
+            _ => {
+                day = day.succ_opt().context("Failed to get next day")?;
+                continue;
+            }
+        };
+        if is_due(tz, day, settings.wish_time, last_check, now)
+            && !db.was_digest_sent(group_id, day).await?
+        {
+            // Without a group language, the digest is written in the language
+            // most of the listed birthday people use
+            let lang = match &settings.language {
+                Some(lang) => lang.clone(),
+                None => {
+                    let birthdays = db
+                        .get_group_birthdays_between(group_id, day, last_day)
+                        .await?
+                        .into_iter()
+                        .filter(|birthday| {
+                            birthday
+                                .next_occurrence(day, settings.leap_day)
+                                .is_some_and(|date| date <= last_day)
+                        });
+                    most_common_lang(birthdays)
+                }
+            };
+            let entries = birthday_entries(bot, db, labels, group_id, &lang, day, last_day).await?;
+            if !entries.is_empty() {
+                for text in split_messages(labels.get(&lang, label), entries) {
+                    bot.send_message(group_id, text)
+                        .await
+                        .with_context(|| format!("Failed to send {}", label))?;
+                }
+            }
+            db.log_digest(group_id, day).await?;
+        }
+        day = day.succ_opt().context("Failed to get next day")?;
+    }
+    Ok(())
+}
+
+/// Returns the language used by most of the birthday people, English if there are none
+fn most_common_lang(birthdays: impl Iterator<Item = Birthday>) -> String {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for birthday in birthdays {
+        *counts.entry(birthday.user_lang).or_default() += 1;
+    }
+    counts
+        .into_iter()
+        // Ties are broken alphabetically, so that the language doesn't change between runs
+        .max_by(|(a, a_count), (b, b_count)| a_count.cmp(b_count).then(b.cmp(a)))
+        .map(|(lang, _)| lang)
+        .unwrap_or_else(|| "en".to_string())
+}
@@ -1,4 +1,5 @@
 // Copyright: https://github.com/hex0x0000/BirthdayBot/src/branch/master/LICENSE
+use crate::template::{self, Text};
 use json::*;
 
 #[derive(Debug)]
@@ -8,21 +9,36 @@ pub struct Langs {
 
 impl Langs {
     pub fn new(json: &str, help_msg: &str) -> Result<Self> {
-        let mut json = match parse(json) {
-            Ok(var) => var,
-            Err(err) => return Err(err),
-        };
+        let mut json = parse(json)?;
         json["en"]["HELP"] = help_msg.into();
         Ok(Self { json })
     }
 
+    /// Returns the codes of the available languages
+    pub fn languages(&self) -> Vec<String> {
+        self.json
+            .entries()
+            .map(|(lang, _)| lang.to_string())
+            .collect()
+    }
+
+    /// Returns the label without placeholders
     pub fn get(&self, lang: &str, msg: &str) -> String {
+        self.render(lang, msg, &[]).text
+    }
+
+    /// Returns the label with its placeholders replaced by `args`
+    pub fn render(&self, lang: &str, msg: &str, args: &[(&str, Text)]) -> Text {
+        template::render(&self.template(lang, msg), args)
+    }
+
+    fn template(&self, lang: &str, msg: &str) -> String {
         if self.json[lang][msg].is_null() {
-            let msg = &self.json["en"][msg];
-            if msg.is_null() {
+            let template = &self.json["en"][msg];
+            if template.is_null() {
                 panic!("{} label doesn't exist", msg);
             }
-            return msg.to_string();
+            return template.to_string();
         }
         self.json[lang][msg].to_string()
     }
@@ -1,8 +1,16 @@
 // Copyright: https://github.com/hex0x0000/BirthdayBot/src/branch/master/LICENSE
+/// Sends a label, replacing its placeholders with the given values.
+/// Example: `send!(bot, labels, chat_id, lang, "MY_BIRTHDAY", date = date, timezone = tz)`
 #[macro_export]
 macro_rules! send {
-    ($bot:expr, $chat_id:expr, $lang:expr, $msg:expr) => {
-        $bot.send_message($chat_id, LABELS.get($lang, $msg))
+    ($bot:expr, $labels:expr, $chat_id:expr, $lang:expr, $msg:expr $(, $name:ident = $value:expr)* $(,)?) => {
+        $labels
+            .render(
+                $lang,
+                $msg,
+                &[$((stringify!($name), $crate::template::Text::from($value))),*],
+            )
+            .send(&$bot, $chat_id)
             .await
             .context(format!("Failed to send {}", $msg))?;
     };
@@ -1,155 +1,409 @@
 // Copyright: https://github.com/hex0x0000/BirthdayBot/src/branch/master/LICENSE
+mod callbacks;
 #[macro_use]
 mod commands;
+mod config;
+mod consent;
 mod database;
-mod globals;
+mod date;
+mod date_picker;
+mod digest;
 mod lang;
 mod macros;
-use crate::{commands::*, database::Database};
+mod migrations;
+mod settings;
+mod subscriptions;
+mod template;
+mod timezone;
+mod usernames;
+use crate::{callbacks::answer_callback, commands::*};
 use anyhow::Context;
-use chrono::{prelude::*, Duration};
-use database::RemoveBirthday;
+use chrono::{prelude::*, Days, TimeDelta};
+use chrono_tz::Tz;
+use config::Config;
+use database::{Birthday, Database, GroupSettings, RemoveBirthday};
+use date_picker::DatePickers;
 use dotenv::dotenv;
-use globals::{Bot, DB, LABELS};
+use lang::Langs;
+use std::collections::{hash_map::Entry, HashMap};
+use std::sync::Arc;
 use teloxide::{
-    adaptors::throttle::Limits,
+    adaptors::{throttle::Limits, CacheMe, Throttle},
+    dispatching::UpdateHandler,
     prelude::*,
-    types::{ChatMember, MessageEntity, MessageEntityKind, Recipient},
+    types::{ChatMember, User},
     utils::command::BotCommands,
     ApiError, RequestError,
 };
+use template::Text;
 use tokio::time::sleep;
 
-fn format(fmt: String, args: &[String]) -> (String, Vec<(usize, usize)>) {
-    let mut new = String::new();
-    let mut args_pos: Vec<(usize, usize)> = vec![];
-    let mut utf16_size: usize = 0;
-    let mut arg_n: usize = 0;
-    for c in fmt.chars().collect::<Vec<char>>().iter() {
-        if *c == '&' && arg_n < args.len() {
-            let len: usize = args[arg_n].chars().map(|x| x.len_utf16()).sum();
-            new.push_str(&args[arg_n]);
-            args_pos.push((utf16_size, len));
-            utf16_size += len;
-            arg_n += 1;
-        } else {
-            new.push(*c);
-            utf16_size += c.len_utf16();
-        }
-    }
-    (new, args_pos)
-}
+/// Seconds between two checks for new birthdays. Groups may choose any
+/// minute of the day as their wish time.
+const CHECK_INTERVAL: i64 = 60;
+const LANGS_JSON: &str = include_str!("../lang.json");
 
-async fn wish_happy_birthday(bot: &Bot) -> anyhow::Result<()> {
-    let now: DateTime<Utc> = Utc::now();
-    let birthdays = DB
-        .get()
-        .await
-        .get_birthdays(now.month(), now.day())
+pub type Bot = CacheMe<Throttle<teloxide::Bot>>;
+
+async fn wish_happy_birthday(
+    bot: &Bot,
+    db: &Database,
+    labels: &Langs,
+    last_check: DateTime<Utc>,
+    now: DateTime<Utc>,
+) -> anyhow::Result<()> {
+    let timezones = db
+        .get_timezones()
         .await
-        .context("Failed to get birthdays")?;
-    for birthday in birthdays {
-        let user: ChatMember = match bot
-            .get_chat_member(
-                Recipient::Id(ChatId(birthday.group_id)),
-                UserId(birthday.user_id as u64),
-            )
-            .await
-        {
-            Ok(user) => user,
+        .context("Failed to get timezones")?;
+    let mut settings_cache: HashMap<ChatId, GroupSettings> = HashMap::new();
+    // Birthdays due in the same group on the same day are wished together
+    let mut wishes: HashMap<(ChatId, NaiveDate), (GroupSettings, Vec<Birthday>)> = HashMap::new();
+    for timezone in timezones {
+        let tz: Tz = match timezone.parse() {
+            Ok(tz) => tz,
             Err(err) => {
-                if let RequestError::Api(api_err) = err {
-                    match api_err {
-                        ApiError::ChatNotFound => {
-                            DB.get()
-                                .await
-                                .rm_birthday(RemoveBirthday::RemoveGroup(birthday.group_id))
-                                .await?;
-                            log::info!("Group removed: {}", birthday.group_id);
-                            continue;
-                        }
-                        ApiError::UserNotFound => {
-                            DB.get()
-                                .await
-                                .rm_birthday(RemoveBirthday::RemoveUserInGroup {
-                                    group_id: birthday.group_id,
-                                    user_id: birthday.user_id,
-                                })
-                                .await?;
-                            log::info!("{} in {} removed", birthday.user_id, birthday.group_id);
+                log::error!("Invalid timezone {}: {}", timezone, err);
+                continue;
+            }
+        };
+        // Birthdays are wished at the group's wish time of the day they fall on, both in the
+        // group's timezone, wishes missed while the bot was offline are caught up. Today's
+        // birthdays are wished as soon as their wish time has passed, even if the group changed
+        // it after the last check, the wish log makes sure they're wished only once.
+        let mut day = last_check
+            .with_timezone(&tz)
+            .date_naive()
+            .pred_opt()
+            .context("Failed to get previous day")?;
+        let today = now.with_timezone(&tz).date_naive();
+        while day <= today {
+            let birthdays = db
+                .get_birthdays(day, timezone.clone())
+                .await
+                .context("Failed to get birthdays")?;
+            for birthday in birthdays.into_iter().flatten() {
+                let settings =
+                    match group_settings(db, &mut settings_cache, birthday.group_id).await {
+                        Ok(settings) => settings,
+                        Err(err) => {
+                            log::error!("Failed to get {}'s settings: {}", birthday.group_id, err);
                             continue;
                         }
-                        _ => log::error!("API error while iterating birthdays: {:?}", api_err),
-                    }
-                    continue;
-                } else {
-                    log::error!("Request error: {:?}", err);
+                    };
+                if !settings.wishes || !is_due(tz, day, settings.wish_time, last_check, now) {
                     continue;
                 }
+                wishes
+                    .entry((birthday.group_id, day))
+                    .or_insert_with(|| (settings.clone(), vec![]))
+                    .1
+                    .push(birthday);
             }
-        };
-        let (fmt_happy_birthday, args_pos) = format(
-            LABELS.get(&birthday.user_lang, "WISH_HAPPY_BDAY"),
-            &[
-                user.user.first_name.clone(),
-                format!("{}", now.year() - birthday.year as i32),
-                format!("{}/{}/{}", now.year(), now.month(), now.day()),
-            ],
-        );
-        let (offset, length) = args_pos[0];
-        let msg: Message = match bot
-            .send_message(Recipient::Id(ChatId(birthday.group_id)), fmt_happy_birthday)
-            .entities(vec![MessageEntity {
-                kind: MessageEntityKind::TextMention { user: user.user },
-                offset,
-                length,
-            }])
-            .await
-        {
-            Ok(msg) => msg,
-            Err(err) => {
-                if let RequestError::Api(api_err) = &err {
-                    match api_err {
-                        ApiError::ChatNotFound => {
-                            DB.get()
-                                .await
-                                .rm_birthday(RemoveBirthday::RemoveGroup(birthday.group_id))
-                                .await?;
-                            log::info!("Group removed: {}", birthday.group_id);
+            // Reminders are sent at the wish time too, some days before the birthday
+            let reminders = db
+                .get_reminders(day, timezone.clone())
+                .await
+                .context("Failed to get reminders")?;
+            for (birthday, days) in reminders {
+                let settings =
+                    match group_settings(db, &mut settings_cache, birthday.group_id).await {
+                        Ok(settings) => settings,
+                        Err(err) => {
+                            log::error!("Failed to get {}'s settings: {}", birthday.group_id, err);
                             continue;
                         }
-                        _ => {}
+                    };
+                if !is_due(tz, day, settings.wish_time, last_check, now) {
+                    continue;
+                }
+                let date = day
+                    .checked_add_days(Days::new(days.into()))
+                    .context("Failed to get reminded day")?;
+                if settings.reminders.contains(&days) {
+                    if let Err(err) =
+                        remind_birthday(bot, db, labels, &birthday, settings, date, days).await
+                    {
+                        log::error!("Failed to remind birthday: {}", err);
+                        log::error!("Root cause: {}", err.root_cause());
                     }
                 }
-                log::error!("Request error: {:?}", err);
-                continue;
+                if let Err(err) =
+                    subscriptions::remind(bot, db, labels, &birthday, settings, date, days).await
+                {
+                    log::error!("Failed to remind subscribers: {}", err);
+                    log::error!("Root cause: {}", err.root_cause());
+                }
             }
-        };
-        if let Err(err) = bot
-            .pin_chat_message(Recipient::Id(ChatId(birthday.group_id)), msg.id)
-            .await
+            day = day.succ_opt().context("Failed to get next day")?;
+        }
+    }
+    for ((group_id, day), (settings, birthdays)) in wishes {
+        if let Err(err) = wish_birthdays(bot, db, labels, group_id, &settings, day, birthdays).await
         {
-            if let RequestError::Api(api_err) = &err {
-                match api_err {
-                    ApiError::NotEnoughRightsToManagePins => {
-                        send!(
-                            bot,
-                            Recipient::Id(ChatId(birthday.group_id)),
-                            &birthday.user_lang,
-                            "NO_PIN_PERM"
-                        );
-                        continue;
-                    }
-                    _ => {}
-                }
+            log::error!("Failed to wish happy birthday: {}", err);
+            log::error!("Root cause: {}", err.root_cause());
+        }
+    }
+    Ok(())
+}
+
+/// Returns the group's settings, getting them from the database only once per check
+async fn group_settings<'a>(
+    db: &Database,
+    cache: &'a mut HashMap<ChatId, GroupSettings>,
+    group_id: ChatId,
+) -> anyhow::Result<&'a GroupSettings> {
+    Ok(match cache.entry(group_id) {
+        Entry::Occupied(entry) => entry.into_mut(),
+        Entry::Vacant(entry) => entry.insert(db.get_group_settings(group_id).await?),
+    })
+}
+
+/// Whether the messages scheduled at `time` of `day` must be sent at this check. Today's are
+/// sent as soon as their time has passed, even if the group changed it after the last check,
+/// so the caller must make sure they're sent only once.
+fn is_due(
+    tz: Tz,
+    day: NaiveDate,
+    time: NaiveTime,
+    last_check: DateTime<Utc>,
+    now: DateTime<Utc>,
+) -> bool {
+    match wish_moment(tz, day, time) {
+        Some(moment) => {
+            moment <= now && (moment > last_check || day == now.with_timezone(&tz).date_naive())
+        }
+        None => false,
+    }
+}
+
+/// Returns when the birthdays of `day` must be wished. Times skipped
+/// by daylight saving time are moved one hour later.
+fn wish_moment(tz: Tz, day: NaiveDate, time: NaiveTime) -> Option<DateTime<Utc>> {
+    let local = day.and_time(time);
+    tz.from_local_datetime(&local)
+        .earliest()
+        .or_else(|| {
+            tz.from_local_datetime(&(local + TimeDelta::try_hours(1)?))
+                .earliest()
+        })
+        .map(|moment| moment.with_timezone(&Utc))
+}
+
+/// Wishes happy birthday to everyone in the group whose birthday is `today`
+/// with a single message
+async fn wish_birthdays(
+    bot: &Bot,
+    db: &Database,
+    labels: &Langs,
+    group_id: ChatId,
+    settings: &GroupSettings,
+    today: NaiveDate,
+    birthdays: Vec<Birthday>,
+) -> anyhow::Result<()> {
+    let mut celebrants: Vec<(Birthday, User)> = vec![];
+    for birthday in birthdays {
+        if db
+            .was_wished(birthday.user_id, group_id, today.year())
+            .await?
+        {
+            continue;
+        }
+        match bot.get_chat_member(group_id, birthday.user_id).await {
+            Ok(member) => celebrants.push((birthday, member.user)),
+            Err(err) if is_group_gone(&err) => {
+                db.rm_birthday(RemoveBirthday::Group(group_id)).await?;
+                log::info!("Group removed: {}", group_id);
+                return Ok(());
+            }
+            Err(RequestError::Api(ApiError::UserNotFound)) => {
+                db.rm_birthday(RemoveBirthday::UserInGroup {
+                    group_id,
+                    user_id: birthday.user_id,
+                })
+                .await?;
+                log::info!("{} in {} removed", birthday.user_id, group_id);
+            }
+            // Telegram would refuse it again at every check
+            Err(RequestError::Api(api_err)) => {
+                log::error!("API error while iterating birthdays: {:?}", api_err);
+                db.log_wish(birthday.user_id, group_id, today.year())
+                    .await?;
+            }
+            Err(err) => log::error!("Request error: {:?}", err),
+        }
+    }
+    let Some((first, _)) = celebrants.first() else {
+        return Ok(());
+    };
+    let lang = settings
+        .language
+        .clone()
+        .unwrap_or_else(|| first.user_lang.clone());
+    let date = format!("{}/{}/{}", today.year(), today.month(), today.day());
+    let text = if let [(birthday, user)] = &celebrants[..] {
+        let name = Text::mention(&user.first_name, user.clone());
+        match birthday.age(today).filter(|_| settings.show_age) {
+            Some(age) => labels.render(
+                &lang,
+                "WISH_HAPPY_BDAY",
+                &[("name", name), ("age", age.into()), ("date", date.into())],
+            ),
+            None => labels.render(
+                &lang,
+                "WISH_HAPPY_BDAY_NO_YEAR",
+                &[("name", name), ("date", date.into())],
+            ),
+        }
+    } else {
+        // Names are listed like "A, B and C", each with their own mention
+        let mut names = Text::default();
+        for (i, (birthday, user)) in celebrants.iter().enumerate() {
+            if i == celebrants.len() - 1 {
+                names.push_str(&labels.get(&lang, "LIST_AND"));
+            } else if i > 0 {
+                names.push_str(", ");
             }
+            let name = Text::mention(&user.first_name, user.clone());
+            names.push(match birthday.age(today).filter(|_| settings.show_age) {
+                Some(age) => labels.render(
+                    &lang,
+                    "WISH_CELEBRANT",
+                    &[("name", name), ("age", age.into())],
+                ),
+                None => name,
+            });
+        }
+        labels.render(
+            &lang,
+            "WISH_HAPPY_BDAY_MANY",
+            &[("names", names), ("date", date.into())],
+        )
+    };
+    let result = text.send(bot, group_id).await;
+    match &result {
+        Err(err) if is_group_gone(err) => {
+            db.rm_birthday(RemoveBirthday::Group(group_id)).await?;
+            log::info!("Group removed: {}", group_id);
+            return Ok(());
+        }
+        // Network errors are retried at the next check, while wishes refused by
+        // Telegram (e.g. when the bot can't write in the group) are given up
+        Err(err @ RequestError::Api(_)) => log::error!("API error while wishing: {:?}", err),
+        Err(err) => {
             log::error!("Request error: {:?}", err);
-            continue;
+            return Ok(());
+        }
+        Ok(_) => {}
+    }
+    for (birthday, _) in &celebrants {
+        db.log_wish(birthday.user_id, group_id, today.year())
+            .await?;
+    }
+    let Ok(msg) = result else {
+        return Ok(());
+    };
+    if !settings.pin {
+        return Ok(());
+    }
+    if let Err(err) = bot.pin_chat_message(group_id, msg.id).await {
+        if let RequestError::Api(ApiError::NotEnoughRightsToManagePins) = &err {
+            send!(bot, labels, group_id, &lang, "NO_PIN_PERM");
+            return Ok(());
         }
+        log::error!("Request error: {:?}", err);
     }
     Ok(())
 }
 
+/// Reminds the group of an upcoming birthday. The birthday person isn't mentioned,
+/// so that they don't get notified of it.
+async fn remind_birthday(
+    bot: &Bot,
+    db: &Database,
+    labels: &Langs,
+    birthday: &Birthday,
+    settings: &GroupSettings,
+    date: NaiveDate,
+    days: u32,
+) -> anyhow::Result<()> {
+    if db
+        .was_reminded(birthday.user_id, birthday.group_id, date.year(), days)
+        .await?
+    {
+        return Ok(());
+    }
+    let user: ChatMember = match bot
+        .get_chat_member(birthday.group_id, birthday.user_id)
+        .await
+    {
+        Ok(user) => user,
+        Err(err) if is_group_gone(&err) => {
+            db.rm_birthday(RemoveBirthday::Group(birthday.group_id))
+                .await?;
+            log::info!("Group removed: {}", birthday.group_id);
+            return Ok(());
+        }
+        Err(err) => {
+            log::error!("Failed to get {}: {:?}", birthday.user_id, err);
+            // Only network errors are worth retrying
+            if let RequestError::Api(_) = err {
+                db.log_reminder(birthday.user_id, birthday.group_id, date.year(), days)
+                    .await?;
+            }
+            return Ok(());
+        }
+    };
+    let lang = settings
+        .language
+        .clone()
+        .unwrap_or_else(|| birthday.user_lang.clone());
+    let countdown = countdown(labels, &lang, days.into());
+    let date_str = format!("{}/{}/{}", date.year(), date.month(), date.day());
+    let text = labels.render(
+        &lang,
+        "BDAY_REMINDER",
+        &[
+            ("name", user.user.first_name.into()),
+            ("countdown", countdown.into()),
+            ("date", date_str.into()),
+        ],
+    );
+    match text.send(bot, birthday.group_id).await {
+        Ok(_) => {
+            db.log_reminder(birthday.user_id, birthday.group_id, date.year(), days)
+                .await?
+        }
+        Err(err) if is_group_gone(&err) => {
+            db.rm_birthday(RemoveBirthday::Group(birthday.group_id))
+                .await?;
+            log::info!("Group removed: {}", birthday.group_id);
+        }
+        // Reminders refused by Telegram are given up, network errors are retried
+        Err(err @ RequestError::Api(_)) => {
+            log::error!("API error while reminding: {:?}", err);
+            db.log_reminder(birthday.user_id, birthday.group_id, date.year(), days)
+                .await?
+        }
+        Err(err) => log::error!("Request error: {:?}", err),
+    }
+    Ok(())
+}
+
+/// Whether the error means that the bot can't reach the group anymore
+fn is_group_gone(err: &RequestError) -> bool {
+    matches!(
+        err,
+        RequestError::Api(
+            ApiError::ChatNotFound
+                | ApiError::BotKicked
+                | ApiError::BotKickedFromSupergroup
+                | ApiError::GroupDeactivated
+        )
+    )
+}
+
 #[tokio::main]
 async fn main() {
     dotenv().ok();
@@ -159,33 +413,71 @@ async fn main() {
     log::info!(
         "This is free software, and you are welcome to redistribute it under certain conditions"
     );
-    Database::new().await.unwrap();
+    let config = Arc::new(Config::from_env().expect("Failed to load config"));
+    let db = Arc::new(
+        Database::new(&config.database_path)
+            .await
+            .expect("Failed to initialize db"),
+    );
+    let labels = Arc::new(
+        Langs::new(LANGS_JSON, &Command::descriptions().to_string())
+            .expect("Failed to initialize langs"),
+    );
     let bot = teloxide::Bot::from_env()
         .throttle(Limits::default())
         .cache_me();
-    let bot_clone = bot.clone();
-    let handler = tokio::spawn(async move {
-        let bot = bot_clone;
-        loop {
-            let now: DateTime<Utc> = Utc::now();
-            let tomorrow: DateTime<Utc> = now
-                .checked_add_signed(
-                    Duration::days(1)
-                        - (Duration::minutes(now.minute().into())
-                            + Duration::hours(now.hour().into())),
-                )
-                .expect("Failed to get tomorrow's date");
-            sleep(tokio::time::Duration::from_secs(
-                (tomorrow.timestamp() - now.timestamp()) as u64,
-            ))
-            .await;
-            log::info!("Starting wish_happy_birthday...");
-            if let Err(err) = wish_happy_birthday(&bot).await {
-                log::error!("Happy birthday wishing failed: {}", err);
-                log::error!("Root cause: {}", err.root_cause());
+    let handler = tokio::spawn({
+        let (bot, db, labels, config) = (bot.clone(), db.clone(), labels.clone(), config.clone());
+        async move {
+            let mut last_check: DateTime<Utc> = Utc::now() - config.catch_up;
+            loop {
+                let now: DateTime<Utc> = Utc::now();
+                log::info!("Starting wish_happy_birthday...");
+                if let Err(err) = wish_happy_birthday(&bot, &db, &labels, last_check, now).await {
+                    log::error!("Happy birthday wishing failed: {}", err);
+                    log::error!("Root cause: {}", err.root_cause());
+                }
+                if let Err(err) = digest::post_digests(&bot, &db, &labels, last_check, now).await {
+                    log::error!("Digest posting failed: {}", err);
+                    log::error!("Root cause: {}", err.root_cause());
+                }
+                if let Err(err) =
+                    consent::expire_requests(&bot, &db, &labels, config.consent_expiry).await
+                {
+                    log::error!("Failed to expire consent requests: {}", err);
+                    log::error!("Root cause: {}", err.root_cause());
+                }
+                last_check = now;
+                let now: DateTime<Utc> = Utc::now();
+                sleep(tokio::time::Duration::from_secs(
+                    (CHECK_INTERVAL - now.timestamp().rem_euclid(CHECK_INTERVAL)) as u64,
+                ))
+                .await;
             }
         }
     });
-    teloxide::commands_repl(bot, answer, Command::ty()).await;
+    Dispatcher::builder(bot, schema())
+        .dependencies(dptree::deps![
+            db,
+            labels,
+            config,
+            Arc::new(DatePickers::default())
+        ])
+        .enable_ctrlc_handler()
+        .build()
+        .dispatch()
+        .await;
     handler.abort();
 }
+
+/// Every kind of update the bot handles is a branch of this tree
+fn schema() -> UpdateHandler<anyhow::Error> {
+    dptree::entry()
+        .inspect_async(usernames::remember_users)
+        .branch(
+            Update::filter_message()
+                .filter_command::<Command>()
+                .endpoint(answer),
+        )
+        .branch(Update::filter_callback_query().endpoint(answer_callback))
+}
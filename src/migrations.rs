@@ -0,0 +1,76 @@
+// Copyright: https://github.com/hex0x0000/BirthdayBot/src/branch/master/LICENSE
+use anyhow::Context;
+use async_sqlite::{
+    rusqlite::{params, OptionalExtension},
+    Pool,
+};
+
+include!(concat!(env!("OUT_DIR"), "/migrations.rs"));
+
+/// Databases created before migrations were tracked already have
+/// every table up to this migration.
+const UNTRACKED_VERSION: i64 = 20261018120000;
+
+/// Applies every migration in `migrations/` newer than the database's schema version.
+/// Fails if the database was created by a newer version of the bot.
+pub async fn migrate(pool: &Pool) -> anyhow::Result<()> {
+    let version = schema_version(pool).await?;
+    let latest = MIGRATIONS.last().map(|(version, _)| *version).unwrap_or(0);
+    if version > latest {
+        anyhow::bail!(
+            "Database schema version {} is newer than the latest supported version {}",
+            version,
+            latest
+        );
+    }
+    for &(migration, sql) in MIGRATIONS
+        .iter()
+        .filter(|(migration, _)| *migration > version)
+    {
+        pool.conn_mut(move |conn| {
+            let tx = conn.transaction()?;
+            tx.execute_batch(sql)?;
+            tx.execute("UPDATE schema_version SET version = ?1", [migration])?;
+            tx.commit()
+        })
+        .await
+        .with_context(|| format!("Failed to apply migration {}", migration))?;
+        log::info!("Applied migration {}", migration);
+    }
+    log::info!("Database schema version: {}", latest);
+    Ok(())
+}
+
+async fn schema_version(pool: &Pool) -> anyhow::Result<i64> {
+    pool.conn_mut(|conn| {
+        let tx = conn.transaction()?;
+        tx.execute(
+            "CREATE TABLE IF NOT EXISTS schema_version (version INTEGER NOT NULL)",
+            [],
+        )?;
+        let version: Option<i64> = tx
+            .query_row("SELECT version FROM schema_version", [], |row| row.get(0))
+            .optional()?;
+        let version = match version {
+            Some(version) => version,
+            None => {
+                // Tables created by the bot before this table existed had a timezone column
+                let untracked: bool = tx.query_row(
+                    "SELECT COUNT(*) FROM pragma_table_info('birthdays') WHERE name = 'timezone'",
+                    [],
+                    |row| row.get(0),
+                )?;
+                let version = if untracked { UNTRACKED_VERSION } else { 0 };
+                tx.execute(
+                    "INSERT INTO schema_version (version) VALUES (?1)",
+                    params![version],
+                )?;
+                version
+            }
+        };
+        tx.commit()?;
+        Ok(version)
+    })
+    .await
+    .context("Failed to get database schema version")
+}
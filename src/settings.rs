@@ -0,0 +1,271 @@
+// Copyright: https://github.com/hex0x0000/BirthdayBot/src/branch/master/LICENSE
+use crate::commands;
+use crate::database::{Database, Digest, GroupSettings, LeapDay};
+use crate::lang::Langs;
+use crate::Bot;
+use anyhow::Context;
+use chrono::TimeDelta;
+use teloxide::{
+    prelude::*,
+    types::{Chat, InlineKeyboardButton, InlineKeyboardMarkup, User},
+    ApiError, RequestError,
+};
+
+/// Minutes the wish time moves with each click
+const WISH_TIME_STEP: i64 = 60;
+
+/// Returns the user's language, English if Telegram doesn't tell it
+pub fn user_lang(user: Option<&User>) -> String {
+    user.and_then(|user| user.language_code.clone())
+        .unwrap_or_else(|| "en".to_string())
+}
+
+/// Returns the language of the messages in the chat: the group's language if it was set,
+/// otherwise the user's
+pub async fn chat_lang(db: &Database, chat: &Chat, user: Option<&User>) -> anyhow::Result<String> {
+    if chat.is_group() || chat.is_supergroup() {
+        if let Some(lang) = db.get_group_settings(chat.id).await?.language {
+            return Ok(lang);
+        }
+    }
+    Ok(user_lang(user))
+}
+
+/// Lists the days before birthdays when reminders are sent, like `7, 1`
+pub fn reminders_list(reminders: &[u32]) -> String {
+    reminders
+        .iter()
+        .map(|days| days.to_string())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+fn on_off(labels: &Langs, lang: &str, value: bool) -> String {
+    labels.get(lang, if value { "SETTINGS_ON" } else { "SETTINGS_OFF" })
+}
+
+fn button(
+    labels: &Langs,
+    lang: &str,
+    label: &str,
+    value: String,
+    data: &str,
+) -> InlineKeyboardButton {
+    let text = labels.render(lang, label, &[("value", value.into())]).text;
+    InlineKeyboardButton::callback(text, format!("settings {}", data))
+}
+
+fn keyboard(
+    labels: &Langs,
+    lang: &str,
+    settings: &GroupSettings,
+    timezone: String,
+) -> InlineKeyboardMarkup {
+    let language = match &settings.language {
+        Some(language) => language.clone(),
+        None => labels.get(lang, "SETTINGS_LANGUAGE_AUTO"),
+    };
+    let add_others = if settings.admins_only_add {
+        labels.get(lang, "SETTINGS_ADMINS")
+    } else {
+        labels.get(lang, "SETTINGS_EVERYONE")
+    };
+    let reminders = if settings.reminders.is_empty() {
+        labels.get(lang, "SETTINGS_OFF")
+    } else {
+        reminders_list(&settings.reminders)
+    };
+    let digest = match settings.digest {
+        Digest::Off => labels.get(lang, "SETTINGS_OFF"),
+        Digest::Weekly => labels.get(lang, "SETTINGS_WEEKLY"),
+        Digest::Monthly => labels.get(lang, "SETTINGS_MONTHLY"),
+    };
+    let leap_day = match settings.leap_day {
+        LeapDay::Feb28 => "28/02",
+        LeapDay::Mar1 => "01/03",
+    };
+    InlineKeyboardMarkup::new([
+        vec![button(
+            labels,
+            lang,
+            "SETTINGS_PIN",
+            on_off(labels, lang, settings.pin),
+            "pin",
+        )],
+        vec![
+            InlineKeyboardButton::callback("⬅️", format!("settings wish_time {}", -WISH_TIME_STEP)),
+            button(
+                labels,
+                lang,
+                "SETTINGS_WISH_TIME",
+                settings.wish_time.format("%H:%M").to_string(),
+                "wish_time 0",
+            ),
+            InlineKeyboardButton::callback("➡️", format!("settings wish_time {}", WISH_TIME_STEP)),
+        ],
+        vec![button(
+            labels,
+            lang,
+            "SETTINGS_LANGUAGE",
+            language,
+            "language",
+        )],
+        vec![button(
+            labels,
+            lang,
+            "SETTINGS_TIMEZONE",
+            timezone,
+            "timezone",
+        )],
+        vec![button(
+            labels,
+            lang,
+            "SETTINGS_WISHES",
+            on_off(labels, lang, settings.wishes),
+            "wishes",
+        )],
+        vec![button(labels, lang, "SETTINGS_DIGEST", digest, "digest")],
+        vec![button(
+            labels,
+            lang,
+            "SETTINGS_REMINDERS",
+            reminders,
+            "reminders",
+        )],
+        vec![button(
+            labels,
+            lang,
+            "SETTINGS_ADD_OTHERS",
+            add_others,
+            "admins_only_add",
+        )],
+        vec![button(
+            labels,
+            lang,
+            "SETTINGS_SKIP_CONSENT",
+            on_off(labels, lang, settings.admins_skip_consent),
+            "admins_skip_consent",
+        )],
+        vec![button(
+            labels,
+            lang,
+            "SETTINGS_SHOW_AGE",
+            on_off(labels, lang, settings.show_age),
+            "show_age",
+        )],
+        vec![button(
+            labels,
+            lang,
+            "SETTINGS_LEAP_DAY",
+            leap_day.to_string(),
+            "leap_day",
+        )],
+        vec![InlineKeyboardButton::callback(
+            labels.get(lang, "SETTINGS_CLOSE"),
+            "settings close",
+        )],
+    ])
+}
+
+/// Sends the group's settings menu
+pub async fn send_menu(
+    bot: &Bot,
+    db: &Database,
+    labels: &Langs,
+    group_id: ChatId,
+    lang: &str,
+) -> anyhow::Result<()> {
+    let settings = db.get_group_settings(group_id).await?;
+    let timezone = db.get_group_timezone(group_id).await?;
+    bot.send_message(group_id, labels.get(lang, "SETTINGS"))
+        .reply_markup(keyboard(labels, lang, &settings, timezone))
+        .await
+        .context("Failed to send SETTINGS")?;
+    Ok(())
+}
+
+/// Handles a click on the settings menu. Returns the label to show to the user
+/// if nothing can be changed with that button.
+pub async fn answer(
+    bot: &Bot,
+    db: &Database,
+    labels: &Langs,
+    query: &CallbackQuery,
+    data: &str,
+    lang: &str,
+) -> anyhow::Result<Option<&'static str>> {
+    let message = query
+        .message
+        .as_ref()
+        .context("Settings menu without message")?;
+    let group_id = message.chat.id;
+    if !commands::is_admin(bot, group_id, query.from.id).await? {
+        return Ok(Some("ERR_DENIED"));
+    }
+    let mut settings = db.get_group_settings(group_id).await?;
+    let (action, value) = data.split_once(' ').unwrap_or((data, ""));
+    match action {
+        "pin" => settings.pin = !settings.pin,
+        "wish_time" => {
+            let minutes: i64 = value.parse().context("Invalid wish time step")?;
+            let step = TimeDelta::try_minutes(minutes).context("Invalid wish time step")?;
+            settings.wish_time = settings.wish_time.overflowing_add_signed(step).0;
+        }
+        "language" => {
+            let languages = labels.languages();
+            // Cycles through every language, then back to each user's language
+            settings.language = match &settings.language {
+                None => languages.first().cloned(),
+                Some(language) => languages
+                    .iter()
+                    .skip_while(|other| *other != language)
+                    .nth(1)
+                    .cloned(),
+            };
+        }
+        "timezone" => return Ok(Some("SETTINGS_TIMEZONE_HELP")),
+        "reminders" => return Ok(Some("SETTINGS_REMINDERS_HELP")),
+        "wishes" => settings.wishes = !settings.wishes,
+        "digest" => {
+            settings.digest = match settings.digest {
+                Digest::Off => Digest::Weekly,
+                Digest::Weekly => Digest::Monthly,
+                Digest::Monthly => Digest::Off,
+            }
+        }
+        "admins_only_add" => settings.admins_only_add = !settings.admins_only_add,
+        "admins_skip_consent" => settings.admins_skip_consent = !settings.admins_skip_consent,
+        "show_age" => settings.show_age = !settings.show_age,
+        "leap_day" => {
+            settings.leap_day = match settings.leap_day {
+                LeapDay::Feb28 => LeapDay::Mar1,
+                LeapDay::Mar1 => LeapDay::Feb28,
+            }
+        }
+        "close" => {
+            match bot
+                .edit_message_text(group_id, message.id, labels.get(lang, "SETTINGS_CLOSED"))
+                .await
+            {
+                Ok(_) | Err(RequestError::Api(ApiError::MessageNotModified)) => {}
+                Err(err) => return Err(err).context("Failed to close SETTINGS"),
+            }
+            return Ok(None);
+        }
+        _ => return Ok(None),
+    }
+    db.set_group_settings(group_id, settings.clone()).await?;
+    let lang = settings
+        .language
+        .clone()
+        .unwrap_or_else(|| user_lang(Some(&query.from)));
+    let timezone = db.get_group_timezone(group_id).await?;
+    match bot
+        .edit_message_text(group_id, message.id, labels.get(&lang, "SETTINGS"))
+        .reply_markup(keyboard(labels, &lang, &settings, timezone))
+        .await
+    {
+        Ok(_) | Err(RequestError::Api(ApiError::MessageNotModified)) => Ok(None),
+        Err(err) => Err(err).context("Failed to edit SETTINGS"),
+    }
+}
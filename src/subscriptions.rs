@@ -0,0 +1,169 @@
+// Copyright: https://github.com/hex0x0000/BirthdayBot/src/branch/master/LICENSE
+use crate::commands::countdown;
+use crate::database::{Birthday, Database, GroupSettings, RemoveBirthday};
+use crate::lang::Langs;
+use crate::send;
+use crate::template::Text;
+use crate::{is_group_gone, Bot};
+use anyhow::Context;
+use chrono::{Datelike, NaiveDate};
+use teloxide::{prelude::*, ApiError, RequestError};
+
+/// Sends the first private message to a new subscriber. Returns `false` if the user
+/// can't get private messages from the bot.
+pub async fn greet(
+    bot: &Bot,
+    labels: &Langs,
+    user_id: UserId,
+    group_title: String,
+    lang: &str,
+    days: u32,
+) -> anyhow::Result<bool> {
+    let text = labels.render(
+        lang,
+        "REMINDME_DM",
+        &[("group", group_title.into()), ("days", days.into())],
+    );
+    match text.send(bot, user_id).await {
+        Ok(_) => Ok(true),
+        Err(RequestError::Api(ApiError::BotBlocked | ApiError::CantInitiateConversation)) => {
+            Ok(false)
+        }
+        Err(err) => Err(err).context("Failed to send REMINDME_DM"),
+    }
+}
+
+/// Tells the subscriber in the group that their reminders were stopped
+async fn disable(
+    bot: &Bot,
+    db: &Database,
+    labels: &Langs,
+    group_id: ChatId,
+    subscriber_id: UserId,
+    lang: &str,
+) -> anyhow::Result<()> {
+    db.disable_subscription(subscriber_id, group_id).await?;
+    let subscriber = match bot.get_chat_member(group_id, subscriber_id).await {
+        Ok(member) => member.user,
+        Err(err) => {
+            log::error!("Failed to get {}: {:?}", subscriber_id, err);
+            return Ok(());
+        }
+    };
+    send!(
+        bot,
+        labels,
+        group_id,
+        lang,
+        "SUBSCRIPTION_DISABLED",
+        name = Text::mention(subscriber.full_name(), subscriber)
+    );
+    Ok(())
+}
+
+/// Privately reminds the group's subscribers of an upcoming birthday,
+/// except for the birthday person
+pub async fn remind(
+    bot: &Bot,
+    db: &Database,
+    labels: &Langs,
+    birthday: &Birthday,
+    settings: &GroupSettings,
+    date: NaiveDate,
+    days: u32,
+) -> anyhow::Result<()> {
+    let mut subscribers = vec![];
+    for (subscriber_id, lang) in db.get_subscribers(birthday.group_id, days).await? {
+        if subscriber_id != birthday.user_id
+            && !db
+                .was_subscriber_reminded(
+                    subscriber_id,
+                    birthday.user_id,
+                    birthday.group_id,
+                    date.year(),
+                )
+                .await?
+        {
+            subscribers.push((subscriber_id, lang));
+        }
+    }
+    if subscribers.is_empty() {
+        return Ok(());
+    }
+    let found = async {
+        let member = bot
+            .get_chat_member(birthday.group_id, birthday.user_id)
+            .await?;
+        let group = bot.get_chat(birthday.group_id).await?;
+        Ok::<_, RequestError>((member.user, group.title().unwrap_or_default().to_string()))
+    }
+    .await;
+    let (user, group_title) = match found {
+        Ok(found) => found,
+        Err(err) if is_group_gone(&err) => {
+            db.rm_birthday(RemoveBirthday::Group(birthday.group_id))
+                .await?;
+            log::info!("Group removed: {}", birthday.group_id);
+            return Ok(());
+        }
+        Err(err) => {
+            log::error!(
+                "Failed to get {} in {}: {:?}",
+                birthday.user_id,
+                birthday.group_id,
+                err
+            );
+            // Only network errors are retried at the next check
+            if let RequestError::Api(_) = err {
+                for (subscriber_id, _) in subscribers {
+                    log_reminder(db, subscriber_id, birthday, date).await?;
+                }
+            }
+            return Ok(());
+        }
+    };
+    let date_str = format!("{}/{}/{}", date.year(), date.month(), date.day());
+    for (subscriber_id, lang) in subscribers {
+        let text = labels.render(
+            &lang,
+            "BDAY_REMINDER_DM",
+            &[
+                ("name", Text::mention(&user.first_name, user.clone())),
+                ("group", group_title.as_str().into()),
+                ("countdown", countdown(labels, &lang, days.into()).into()),
+                ("date", date_str.as_str().into()),
+            ],
+        );
+        match text.send(bot, subscriber_id).await {
+            Ok(_) => log_reminder(db, subscriber_id, birthday, date).await?,
+            // Users must start the bot in private chat before it can message them
+            Err(RequestError::Api(ApiError::BotBlocked | ApiError::CantInitiateConversation)) => {
+                log_reminder(db, subscriber_id, birthday, date).await?;
+                let lang = settings.language.as_deref().unwrap_or(&lang);
+                disable(bot, db, labels, birthday.group_id, subscriber_id, lang).await?;
+            }
+            Err(err @ RequestError::Api(_)) => {
+                log::error!("API error while reminding subscriber: {:?}", err);
+                log_reminder(db, subscriber_id, birthday, date).await?;
+            }
+            Err(err) => log::error!("Request error: {:?}", err),
+        }
+    }
+    Ok(())
+}
+
+/// Marks the subscriber as done with the birthday, so that they aren't reminded again
+async fn log_reminder(
+    db: &Database,
+    subscriber_id: UserId,
+    birthday: &Birthday,
+    date: NaiveDate,
+) -> anyhow::Result<()> {
+    db.log_subscriber_reminder(
+        subscriber_id,
+        birthday.user_id,
+        birthday.group_id,
+        date.year(),
+    )
+    .await
+}
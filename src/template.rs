@@ -0,0 +1,216 @@
+// Copyright: https://github.com/hex0x0000/BirthdayBot/src/branch/master/LICENSE
+//! Labels are templates with named placeholders like `{name}`, which are replaced
+//! by the values given for them. `{{` and `}}` are a literal `{` and `}`.
+use crate::Bot;
+use teloxide::{
+    prelude::*,
+    types::{MessageEntity, MessageEntityKind, Recipient, User},
+};
+
+/// Text with its entities, like mentions
+#[derive(Clone, Debug, Default)]
+pub struct Text {
+    pub text: String,
+    pub entities: Vec<MessageEntity>,
+}
+
+impl Text {
+    /// The user's name, mentioning them
+    pub fn mention(name: impl Into<String>, user: User) -> Self {
+        let text: String = name.into();
+        let length = utf16_len(&text);
+        Self {
+            text,
+            entities: vec![MessageEntity {
+                kind: MessageEntityKind::TextMention { user },
+                offset: 0,
+                length,
+            }],
+        }
+    }
+
+    pub fn push_str(&mut self, text: &str) {
+        self.text.push_str(text);
+    }
+
+    /// Appends the text, moving its entities after the current text
+    pub fn push(&mut self, text: Text) {
+        let offset = utf16_len(&self.text);
+        self.entities
+            .extend(text.entities.into_iter().map(|entity| MessageEntity {
+                offset: entity.offset + offset,
+                ..entity
+            }));
+        self.text.push_str(&text.text);
+    }
+
+    /// Sends the text with its entities
+    pub fn send(self, bot: &Bot, chat_id: impl Into<Recipient>) -> <Bot as Requester>::SendMessage {
+        let request = bot.send_message(chat_id, self.text);
+        if self.entities.is_empty() {
+            request
+        } else {
+            request.entities(self.entities)
+        }
+    }
+}
+
+impl From<String> for Text {
+    fn from(text: String) -> Self {
+        Self {
+            text,
+            entities: vec![],
+        }
+    }
+}
+
+impl From<&str> for Text {
+    fn from(text: &str) -> Self {
+        text.to_string().into()
+    }
+}
+
+macro_rules! text_from_number {
+    ($($number:ty),*) => {
+        $(impl From<$number> for Text {
+            fn from(number: $number) -> Self {
+                number.to_string().into()
+            }
+        })*
+    };
+}
+
+text_from_number!(i32, i64, u32, usize);
+
+/// Telegram measures entities in UTF-16 code units
+fn utf16_len(text: &str) -> usize {
+    text.encode_utf16().count()
+}
+
+/// Replaces the placeholders in the template with their values. Placeholders
+/// without a value and unmatched braces are left as they are.
+pub fn render(template: &str, args: &[(&str, Text)]) -> Text {
+    let mut rendered = Text::default();
+    let mut rest = template;
+    while let Some(start) = rest.find(['{', '}']) {
+        rendered.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if rest.starts_with("{{") || rest.starts_with("}}") {
+            rendered.push_str(&rest[..1]);
+            rest = &rest[2..];
+            continue;
+        }
+        let placeholder = rest
+            .strip_prefix('{')
+            .and_then(|after| after.find('}').map(|end| &after[..end]))
+            .and_then(|name| args.iter().find(|(arg, _)| *arg == name));
+        match placeholder {
+            Some((name, value)) => {
+                rendered.push(value.clone());
+                rest = &rest[name.len() + 2..];
+            }
+            None => {
+                rendered.push_str(&rest[..1]);
+                rest = &rest[1..];
+            }
+        }
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(id: u64, name: &str) -> User {
+        User {
+            id: UserId(id),
+            is_bot: false,
+            first_name: name.to_string(),
+            last_name: None,
+            username: None,
+            language_code: None,
+            is_premium: false,
+            added_to_attachment_menu: false,
+        }
+    }
+
+    /// Offset and length of each entity, with the mentioned user's id
+    fn mentions(text: &Text) -> Vec<(usize, usize, u64)> {
+        text.entities
+            .iter()
+            .map(|entity| match &entity.kind {
+                MessageEntityKind::TextMention { user } => {
+                    (entity.offset, entity.length, user.id.0)
+                }
+                kind => panic!("Unexpected entity {:?}", kind),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn replaces_placeholders() {
+        let text = render(
+            "Happy birthday {name}, you're {age}!",
+            &[("name", "Anna".into()), ("age", 30.into())],
+        );
+        assert_eq!(text.text, "Happy birthday Anna, you're 30!");
+        assert!(text.entities.is_empty());
+    }
+
+    #[test]
+    fn escapes_braces() {
+        let text = render("{{name}} is {name} }}{{", &[("name", "Anna".into())]);
+        assert_eq!(text.text, "{name} is Anna }{");
+    }
+
+    #[test]
+    fn keeps_unknown_placeholders_and_unmatched_braces() {
+        let args = [("name", Text::from("Anna"))];
+        assert_eq!(render("{unknown} {name}", &args).text, "{unknown} Anna");
+        assert_eq!(render("{name", &args).text, "{name");
+        assert_eq!(render("name} {", &args).text, "name} {");
+        assert_eq!(render("{}", &args).text, "{}");
+        assert_eq!(render("{{name}", &args).text, "{name}");
+    }
+
+    #[test]
+    fn mention_offsets_are_in_utf16_after_emoji() {
+        let text = render(
+            "🎂🎂 Happy birthday {name}!",
+            &[("name", Text::mention("Anna", user(1, "Anna")))],
+        );
+        assert_eq!(text.text, "🎂🎂 Happy birthday Anna!");
+        // Each 🎂 is two UTF-16 code units
+        assert_eq!(mentions(&text), vec![(20, 4, 1)]);
+    }
+
+    #[test]
+    fn mention_length_is_in_utf16() {
+        let text = Text::mention("Zoë 🎈", user(1, "Zoë 🎈"));
+        assert_eq!(mentions(&text), vec![(0, 6, 1)]);
+    }
+
+    #[test]
+    fn pushes_keep_every_mention() {
+        let mut names = Text::default();
+        names.push(Text::mention("🦊 Fox", user(1, "🦊 Fox")));
+        names.push_str(", ");
+        names.push(Text::mention("Bob", user(2, "Bob")));
+        names.push_str(" and ");
+        names.push(Text::mention("Carl", user(3, "Carl")));
+        let text = render("🎉 {names}: happy birthday!", &[("names", names)]);
+        assert_eq!(text.text, "🎉 🦊 Fox, Bob and Carl: happy birthday!");
+        assert_eq!(mentions(&text), vec![(3, 6, 1), (11, 3, 2), (19, 4, 3)]);
+    }
+
+    #[test]
+    fn repeated_placeholders_repeat_mentions() {
+        let text = render(
+            "{name} {name}",
+            &[("name", Text::mention("Anna", user(1, "Anna")))],
+        );
+        assert_eq!(mentions(&text), vec![(0, 4, 1), (5, 4, 1)]);
+    }
+}
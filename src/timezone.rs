@@ -0,0 +1,86 @@
+// Copyright: https://github.com/hex0x0000/BirthdayBot/src/branch/master/LICENSE
+use chrono_tz::{Tz, TZ_VARIANTS};
+
+const MAX_SUGGESTIONS: usize = 5;
+
+/// Parses a timezone given either as an IANA name (`Europe/Rome`, case insensitive)
+/// or as a whole-hour UTC offset (`+2`, `-8`, `UTC+5`, `GMT-3`), which is mapped to
+/// its `Etc/GMT±N` equivalent.
+/// If the timezone is unknown returns up to `MAX_SUGGESTIONS` similarly named timezones.
+pub fn parse_timezone(timezone: &str) -> Result<Tz, Vec<&'static str>> {
+    let timezone = timezone.trim().replace(' ', "_");
+    let offset = timezone
+        .strip_prefix("UTC")
+        .or_else(|| timezone.strip_prefix("GMT"))
+        .unwrap_or(&timezone);
+    if offset.is_empty() {
+        return Ok(Tz::UTC);
+    }
+    if let Ok(offset) = offset.parse::<i8>() {
+        return from_offset(offset).ok_or_else(Vec::new);
+    }
+    if let Some(tz) = TZ_VARIANTS
+        .iter()
+        .find(|tz| tz.name().eq_ignore_ascii_case(&timezone))
+    {
+        return Ok(*tz);
+    }
+    let timezone = timezone.to_lowercase();
+    Err(TZ_VARIANTS
+        .iter()
+        .map(|tz| tz.name())
+        .filter(|name| name.to_lowercase().contains(&timezone))
+        .take(MAX_SUGGESTIONS)
+        .collect())
+}
+
+/// Converts a whole-hour UTC offset into the matching `Etc/GMT±N` timezone.
+/// Note that the sign of `Etc` timezones is inverted: UTC+2 is `Etc/GMT-2`.
+fn from_offset(offset: i8) -> Option<Tz> {
+    if offset == 0 {
+        return Some(Tz::UTC);
+    }
+    format!("Etc/GMT{:+}", -offset).parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_names_case_insensitively() {
+        assert_eq!(parse_timezone("Europe/Rome"), Ok(Tz::Europe__Rome));
+        assert_eq!(parse_timezone(" europe/rome "), Ok(Tz::Europe__Rome));
+        assert_eq!(
+            parse_timezone("America/New York"),
+            Ok(Tz::America__New_York)
+        );
+        assert_eq!(parse_timezone("UTC"), Ok(Tz::UTC));
+        assert_eq!(parse_timezone("GMT"), Ok(Tz::UTC));
+    }
+
+    #[test]
+    fn parses_offsets_with_inverted_etc_sign() {
+        assert_eq!(parse_timezone("+2"), Ok(Tz::Etc__GMTMinus2));
+        assert_eq!(parse_timezone("-8"), Ok(Tz::Etc__GMTPlus8));
+        assert_eq!(parse_timezone("UTC+5"), Ok(Tz::Etc__GMTMinus5));
+        assert_eq!(parse_timezone("GMT-3"), Ok(Tz::Etc__GMTPlus3));
+        assert_eq!(parse_timezone("0"), Ok(Tz::UTC));
+        assert_eq!(parse_timezone("+14"), Ok(Tz::Etc__GMTMinus14));
+    }
+
+    #[test]
+    fn rejects_offsets_out_of_range() {
+        assert_eq!(parse_timezone("+15"), Err(vec![]));
+        assert_eq!(parse_timezone("-13"), Err(vec![]));
+        assert_eq!(parse_timezone("+1000"), Err(vec![]));
+    }
+
+    #[test]
+    fn suggests_similar_names() {
+        let suggestions = parse_timezone("rome").unwrap_err();
+        assert!(suggestions.contains(&"Europe/Rome"));
+        assert!(suggestions.len() <= MAX_SUGGESTIONS);
+        assert_eq!(parse_timezone("Nowhere/Atlantis"), Err(vec![]));
+    }
+}
@@ -0,0 +1,68 @@
+// Copyright: https://github.com/hex0x0000/BirthdayBot/src/branch/master/LICENSE
+use crate::database::Database;
+use std::sync::Arc;
+use teloxide::{
+    prelude::*,
+    types::{MessageEntityKind, UpdateKind, User},
+};
+
+/// Telegram usernames are 5 to 32 characters long and contain only letters, digits and underscores
+pub fn is_username_valid(username: &str) -> bool {
+    (5..=32).contains(&username.len())
+        && username.starts_with(|c: char| c.is_ascii_alphabetic())
+        && username
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Returns every user that appears in the message
+fn message_users(message: &Message) -> Vec<&User> {
+    let mut users: Vec<&User> = message.from().into_iter().collect();
+    if let Some(reply) = message.reply_to_message() {
+        users.extend(reply.from());
+    }
+    if let Some(user) = message.forward_from_user() {
+        users.push(user);
+    }
+    if let Some(members) = message.new_chat_members() {
+        users.extend(members);
+    }
+    if let Some(entities) = message.entities() {
+        for entity in entities {
+            if let MessageEntityKind::TextMention { user } = &entity.kind {
+                users.push(user);
+            }
+        }
+    }
+    users
+}
+
+/// Returns every user that appears in the update
+fn update_users(update: &Update) -> Vec<&User> {
+    let mut users: Vec<&User> = match &update.kind {
+        UpdateKind::Message(message) => message_users(message),
+        UpdateKind::ChatMember(member) | UpdateKind::MyChatMember(member) => {
+            vec![&member.from, &member.new_chat_member.user]
+        }
+        _ => vec![],
+    };
+    users.extend(update.user());
+    users
+}
+
+/// Remembers the usernames of the users the bot sees, so that they can be tagged
+/// with @username in commands
+pub async fn remember_users(update: Update, db: Arc<Database>) {
+    let users: Vec<(UserId, Option<String>)> = update_users(&update)
+        .into_iter()
+        .filter(|user| !user.is_bot)
+        .map(|user| (user.id, user.username.clone()))
+        .collect();
+    if users.is_empty() {
+        return;
+    }
+    if let Err(err) = db.remember_usernames(users).await {
+        log::error!("Failed to remember usernames: {}", err);
+        log::error!("Root cause: {}", err.root_cause());
+    }
+}